@@ -0,0 +1,220 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// Contents of one filesystem block, shared between the cache and its readers.
+pub type Block = Arc<[u8]>;
+
+/// Default byte budget of the block cache of a `Disk`.
+pub const DEFAULT_CACHE_BYTES: usize = 8 << 20;
+
+/// Hit and miss counts of a `BlockCache`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Least recently used cache of blocks, bounded by the total size of the
+/// blocks it holds. A budget of 0 disables it.
+#[derive(Debug)]
+pub struct BlockCache {
+    capacity: usize,
+    used: usize,
+    /// Block number to contents and the tick of its last use.
+    blocks: HashMap<u64, (Block, u64)>,
+    /// Tick of last use to block number, oldest first.
+    lru: BTreeMap<u64, u64>,
+    tick: u64,
+    stats: CacheStats,
+}
+
+impl BlockCache {
+    /// A cache holding at most `capacity` bytes of blocks.
+    pub fn new(capacity: usize) -> Self {
+        BlockCache {
+            capacity,
+            used: 0,
+            blocks: HashMap::new(),
+            lru: BTreeMap::new(),
+            tick: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Changes the budget, evicting blocks until it is met.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict(0);
+    }
+
+    /// Bytes of blocks currently held.
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Looks up `block_num`, marking it as most recently used.
+    pub fn get(&mut self, block_num: u64) -> Option<Block> {
+        let tick = self.next_tick();
+        match self.blocks.get_mut(&block_num) {
+            Some((block, last_use)) => {
+                self.lru.remove(last_use);
+                self.lru.insert(tick, block_num);
+                *last_use = tick;
+                self.stats.hits += 1;
+                Some(block.clone())
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Caches `block` as the contents of `block_num`. Blocks larger than the
+    /// whole budget are not kept.
+    pub fn insert(&mut self, block_num: u64, block: Block) {
+        self.remove(block_num);
+        if block.len() > self.capacity {
+            return;
+        }
+        self.evict(block.len());
+        let tick = self.next_tick();
+        self.used += block.len();
+        self.lru.insert(tick, block_num);
+        self.blocks.insert(block_num, (block, tick));
+    }
+
+    /// Drops `block_num` from the cache, e.g. after it was overwritten.
+    pub fn remove(&mut self, block_num: u64) {
+        if let Some((block, last_use)) = self.blocks.remove(&block_num) {
+            self.lru.remove(&last_use);
+            self.used -= block.len();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+        self.lru.clear();
+        self.used = 0;
+    }
+
+    /// Evicts the least recently used blocks until `incoming` more bytes fit.
+    fn evict(&mut self, incoming: usize) {
+        while self.used + incoming > self.capacity {
+            let Some((_, block_num)) = self.lru.pop_first() else {
+                break;
+            };
+            if let Some((block, _)) = self.blocks.remove(&block_num) {
+                self.used -= block.len();
+            }
+        }
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(len: usize, fill: u8) -> Block {
+        vec![fill; len].into()
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = BlockCache::new(3 * 1024);
+        for n in 0..3 {
+            cache.insert(n, block(1024, n as u8));
+        }
+        assert!(cache.get(0).is_some());
+        cache.insert(3, block(1024, 3));
+
+        assert!(cache.get(1).is_none());
+        assert_eq!(cache.get(0).unwrap()[0], 0);
+        assert_eq!(cache.get(2).unwrap()[0], 2);
+        assert_eq!(cache.get(3).unwrap()[0], 3);
+        assert_eq!(cache.used(), 3 * 1024);
+    }
+
+    #[test]
+    fn stays_within_byte_budget() {
+        let mut cache = BlockCache::new(4096);
+        cache.insert(0, block(1024, 0));
+        cache.insert(1, block(1024, 1));
+        cache.insert(2, block(4096, 2));
+        assert_eq!(cache.used(), 4096);
+        assert!(cache.get(0).is_none());
+        assert!(cache.get(1).is_none());
+
+        cache.insert(3, block(1024, 3));
+        assert_eq!(cache.used(), 1024);
+        assert!(cache.get(2).is_none());
+    }
+
+    #[test]
+    fn replacing_a_block_keeps_accounting() {
+        let mut cache = BlockCache::new(4096);
+        cache.insert(7, block(1024, 1));
+        cache.insert(7, block(2048, 2));
+        assert_eq!(cache.used(), 2048);
+        assert_eq!(cache.get(7).unwrap()[0], 2);
+
+        cache.remove(7);
+        assert_eq!(cache.used(), 0);
+        assert!(cache.get(7).is_none());
+    }
+
+    #[test]
+    fn oversized_blocks_are_not_kept() {
+        let mut cache = BlockCache::new(1024);
+        cache.insert(0, block(1024, 0));
+        cache.insert(1, block(2048, 1));
+        assert!(cache.get(1).is_none());
+        assert!(cache.get(0).is_some());
+
+        let mut disabled = BlockCache::new(0);
+        disabled.insert(0, block(1, 0));
+        assert!(disabled.get(0).is_none());
+        assert_eq!(disabled.used(), 0);
+    }
+
+    #[test]
+    fn shrinking_evicts_oldest() {
+        let mut cache = BlockCache::new(4096);
+        for n in 0..4 {
+            cache.insert(n, block(1024, n as u8));
+        }
+        cache.set_capacity(2048);
+        assert_eq!(cache.used(), 2048);
+        assert!(cache.get(0).is_none());
+        assert!(cache.get(1).is_none());
+        assert!(cache.get(2).is_some());
+        assert!(cache.get(3).is_some());
+    }
+
+    #[test]
+    fn counts_hits_and_misses() {
+        let mut cache = BlockCache::new(4096);
+        cache.insert(0, block(1024, 0));
+        cache.get(0);
+        cache.get(0);
+        cache.get(1);
+        assert_eq!(cache.stats(), CacheStats { hits: 2, misses: 1 });
+
+        cache.clear();
+        assert_eq!(cache.used(), 0);
+        assert!(cache.get(0).is_none());
+    }
+}
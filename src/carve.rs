@@ -0,0 +1,248 @@
+use crate::device::BlockDevice;
+use crate::disk::check_super_block;
+use crate::error::{Ext4Error, Result};
+use crate::ext4;
+use crate::ext4::LoadAble;
+
+/// Size of a superblock on disk.
+const SUPER_BLOCK_SIZE: usize = 1024;
+
+/// Offset of the primary superblock from the start of a filesystem.
+const PRIMARY_OFFSET: u64 = 0x400;
+
+/// Offset of s_magic inside a superblock.
+const MAGIC_OFFSET: usize = 0x38;
+
+/// Alignment filesystems are searched at by default: one sector.
+pub const DEFAULT_ALIGN: u64 = 512;
+
+/// Bytes scanned per device read.
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// How a superblock copy compares with the superblock a candidate was found by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyStatus {
+    /// Same filesystem identity and geometry.
+    Matches,
+    /// A superblock that disagrees on the UUID or geometry.
+    Differs,
+    /// No superblock magic where the copy should be.
+    Missing,
+    /// The copy lies past the end of the device.
+    OutOfRange,
+}
+
+/// One superblock copy a candidate filesystem should have.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SuperBlockCopy {
+    pub group: u32,
+    /// Byte offset of the copy on the device.
+    pub offset: u64,
+    pub status: CopyStatus,
+}
+
+/// A filesystem found by its superblock.
+#[derive(Debug)]
+pub struct Candidate {
+    /// Byte offset of block 0 of the filesystem on the device.
+    pub offset: u64,
+    /// Group whose superblock copy led to the candidate; 0 for the primary.
+    pub found_in_group: u32,
+    pub super_block: ext4::structs::SuperBlock,
+    /// Every copy of the superblock the filesystem should have, primary first.
+    pub copies: Vec<SuperBlockCopy>,
+}
+
+impl Candidate {
+    /// Size of the filesystem in bytes.
+    pub fn size(&self) -> u64 {
+        // Candidates are only made from superblocks whose size fits.
+        fs_size(&self.super_block).unwrap_or(u64::MAX)
+    }
+
+    /// Whether every superblock copy is present and agrees.
+    pub fn is_consistent(&self) -> bool {
+        self.copies.iter().all(|c| c.status == CopyStatus::Matches)
+    }
+}
+
+/// Searches `device` for ext4 superblocks starting every `align` bytes and
+/// returns the filesystems they describe, in order of offset. Each one is
+/// reported once, from its primary superblock if that survived and from
+/// the first intact backup otherwise. A copy whose UUID disagrees counts as
+/// another filesystem and is reported as well. Backups implying a start
+/// before the beginning of the device are ignored, as are superblocks
+/// describing a filesystem larger than the whole device.
+pub fn find_filesystems<D: BlockDevice + ?Sized>(
+    device: &mut D,
+    align: u64,
+) -> Result<Vec<Candidate>> {
+    if align == 0 || align > CHUNK_SIZE as u64 {
+        return Err(Ext4Error::Unsupported(format!(
+            "scan alignment of {} bytes",
+            align
+        )));
+    }
+    let size = device.size()?;
+    let mut candidates: Vec<Candidate> = Vec::new();
+    let mut chunk_start = 0;
+    while chunk_start < size {
+        // Each chunk overlaps the next by a superblock, so none is split.
+        let len = (size - chunk_start).min((CHUNK_SIZE + SUPER_BLOCK_SIZE) as u64) as usize;
+        let mut chunk = vec![0u8; len];
+        device.read_at(chunk_start, &mut chunk)?;
+
+        let mut pos = chunk_start.next_multiple_of(align);
+        while pos < chunk_start + CHUNK_SIZE as u64 && pos < size {
+            let at = (pos - chunk_start) as usize;
+            if chunk.get(at + MAGIC_OFFSET..at + MAGIC_OFFSET + 2) == Some(&[0x53, 0xEF]) {
+                if let Some(c) = candidate_at(device, &chunk, at, pos, &candidates)? {
+                    candidates.push(c);
+                }
+            }
+            pos += align;
+        }
+        chunk_start += CHUNK_SIZE as u64;
+    }
+    candidates.sort_by_key(|c| c.offset);
+    Ok(candidates)
+}
+
+/// The candidate described by a superblock magic hit at `pos`, or `None`
+/// if the superblock is implausible or its filesystem is already known.
+fn candidate_at<D: BlockDevice + ?Sized>(
+    device: &mut D,
+    chunk: &[u8],
+    at: usize,
+    pos: u64,
+    known: &[Candidate],
+) -> Result<Option<Candidate>> {
+    let sb = match ext4::structs::SuperBlock::from_buffer(chunk, at) {
+        Ok(sb) if is_plausible(&sb) => sb,
+        _ => return Ok(None),
+    };
+    // A filesystem larger than the whole device cannot be on it.
+    let device_size = device.size()?;
+    if fs_size(&sb).is_none_or(|size| size > device_size) {
+        return Ok(None);
+    }
+    let group = sb.s_block_group_nr as u32;
+    let offset = match copy_offset(&sb, group).and_then(|copy| pos.checked_sub(copy)) {
+        Some(offset) => offset,
+        None => return Ok(None),
+    };
+    if known
+        .iter()
+        .any(|c| c.offset == offset && c.super_block.s_uuid == sb.s_uuid)
+    {
+        return Ok(None);
+    }
+
+    let mut copies = Vec::new();
+    for g in sb.super_block_groups() {
+        let Some(copy) = copy_offset(&sb, g).and_then(|c| offset.checked_add(c)) else {
+            return Ok(None);
+        };
+        let status = if g == group {
+            CopyStatus::Matches
+        } else {
+            copy_status(device, &sb, copy)?
+        };
+        copies.push(SuperBlockCopy {
+            group: g,
+            offset: copy,
+            status,
+        });
+    }
+    Ok(Some(Candidate {
+        offset,
+        found_in_group: group,
+        super_block: sb,
+        copies,
+    }))
+}
+
+/// Size in bytes of the filesystem `sb` describes, or `None` if it does
+/// not fit in a u64.
+fn fs_size(sb: &ext4::structs::SuperBlock) -> Option<u64> {
+    let block_size = 1_u64.checked_shl(10 + sb.s_log_block_size)?;
+    sb.blocks_count().checked_mul(block_size)
+}
+
+/// Offset from the start of the filesystem of the superblock copy in
+/// group `group`, or `None` if it does not fit in a u64. Backups sit at
+/// the start of their group's first block.
+fn copy_offset(sb: &ext4::structs::SuperBlock, group: u32) -> Option<u64> {
+    if group == 0 {
+        return Some(PRIMARY_OFFSET);
+    }
+    let block_size = 1_u64.checked_shl(10 + sb.s_log_block_size)?;
+    let block = group as u64 * sb.s_blocks_per_group as u64 + sb.s_first_data_block as u64;
+    block.checked_mul(block_size)
+}
+
+fn copy_status<D: BlockDevice + ?Sized>(
+    device: &mut D,
+    sb: &ext4::structs::SuperBlock,
+    offset: u64,
+) -> Result<CopyStatus> {
+    if offset + SUPER_BLOCK_SIZE as u64 > device.size()? {
+        return Ok(CopyStatus::OutOfRange);
+    }
+    let copy = ext4::structs::SuperBlock::from_device_offset(device, offset)?;
+    Ok(if copy.s_magic != sb.s_magic {
+        CopyStatus::Missing
+    } else if same_filesystem(sb, &copy) {
+        CopyStatus::Matches
+    } else {
+        CopyStatus::Differs
+    })
+}
+
+/// Whether two superblocks agree on what stays fixed between copies:
+/// identity and geometry, but not counters, times or state.
+pub(crate) fn same_filesystem(
+    a: &ext4::structs::SuperBlock,
+    b: &ext4::structs::SuperBlock,
+) -> bool {
+    a.s_uuid == b.s_uuid
+        && a.blocks_count() == b.blocks_count()
+        && a.s_inodes_count == b.s_inodes_count
+        && a.s_log_block_size == b.s_log_block_size
+        && a.s_first_data_block == b.s_first_data_block
+        && a.s_blocks_per_group == b.s_blocks_per_group
+        && a.s_inodes_per_group == b.s_inodes_per_group
+        && a.s_feature_incompat == b.s_feature_incompat
+}
+
+/// Checks that a superblock with the right magic describes a filesystem
+/// that could exist, so stray 0xEF53 bytes are not reported.
+fn is_plausible(sb: &ext4::structs::SuperBlock) -> bool {
+    use ext4::flags::superblock::{CompatibleFeatures, IncompatibleFeatures, ROCompatibleFeatures};
+
+    // This covers the group geometry and inode count as well.
+    match check_super_block(sb) {
+        Ok(()) | Err(Ext4Error::Unsupported(_)) => {}
+        Err(_) => return false,
+    }
+    if sb.s_rev_level > 1 {
+        return false;
+    }
+    // Unknown feature bits are far more likely noise than a newer kernel.
+    if !CompatibleFeatures::all().contains(sb.s_feature_compat)
+        || !IncompatibleFeatures::all().contains(sb.s_feature_incompat)
+        || !ROCompatibleFeatures::all().contains(sb.s_feature_ro_compat)
+    {
+        return false;
+    }
+
+    let bigalloc = sb
+        .s_feature_ro_compat
+        .contains(ROCompatibleFeatures::BIGALLOC);
+    // Block 0 holds the superblock itself unless blocks are 1KiB.
+    let first_data_block = (sb.s_log_block_size == 0 && !bigalloc) as u32;
+    let groups = sb.group_count();
+    sb.s_first_data_block == first_data_block
+        && (sb.s_block_group_nr as u32) < groups
+        && sb.group_has_super(sb.s_block_group_nr as u32)
+}
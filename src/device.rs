@@ -0,0 +1,212 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/// Random-access storage an ext4 image can be read from.
+pub trait BlockDevice {
+    /// Fills `buf` with the bytes starting at `offset`. Reading past the
+    /// end fails with `UnexpectedEof`.
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()>;
+
+    /// Writes all of `buf` starting at `offset`.
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<()>;
+
+    /// Size of the device in bytes.
+    fn size(&mut self) -> io::Result<u64>;
+}
+
+fn past_end() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "access past the end of the device",
+    )
+}
+
+impl BlockDevice for File {
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        self.seek(SeekFrom::Start(offset))?;
+        self.read_exact(buf)
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
+        self.seek(SeekFrom::Start(offset))?;
+        self.write_all(buf)
+    }
+
+    fn size(&mut self) -> io::Result<u64> {
+        // Block device nodes report a zero length in their metadata.
+        self.seek(SeekFrom::End(0))
+    }
+}
+
+/// An in-memory image. Writes past the end grow it.
+impl BlockDevice for Vec<u8> {
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        let start = usize::try_from(offset).map_err(|_| past_end())?;
+        let src = start
+            .checked_add(buf.len())
+            .and_then(|end| self.get(start..end))
+            .ok_or_else(past_end)?;
+        buf.copy_from_slice(src);
+        Ok(())
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
+        let start = usize::try_from(offset).map_err(|_| past_end())?;
+        let end = start.checked_add(buf.len()).ok_or_else(past_end)?;
+        if end > self.len() {
+            self.resize(end, 0);
+        }
+        self[start..end].copy_from_slice(buf);
+        Ok(())
+    }
+
+    fn size(&mut self) -> io::Result<u64> {
+        Ok(self.len() as u64)
+    }
+}
+
+impl<D: BlockDevice + ?Sized> BlockDevice for &mut D {
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        (**self).read_at(offset, buf)
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
+        (**self).write_at(offset, buf)
+    }
+
+    fn size(&mut self) -> io::Result<u64> {
+        (**self).size()
+    }
+}
+
+impl<D: BlockDevice + ?Sized> BlockDevice for Box<D> {
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        (**self).read_at(offset, buf)
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
+        (**self).write_at(offset, buf)
+    }
+
+    fn size(&mut self) -> io::Result<u64> {
+        (**self).size()
+    }
+}
+
+/// A byte range of another device, such as a partition inside a whole-disk
+/// image. Offsets are relative to the start of the range, and accesses may
+/// not cross its end.
+pub struct SubDevice<D> {
+    inner: D,
+    offset: u64,
+    len: u64,
+}
+
+impl<D: BlockDevice> SubDevice<D> {
+    /// The `len` bytes of `inner` starting at `offset`.
+    pub fn new(inner: D, offset: u64, len: u64) -> Self {
+        SubDevice { inner, offset, len }
+    }
+
+    /// Everything in `inner` from `offset` to its end.
+    pub fn from_offset(mut inner: D, offset: u64) -> io::Result<Self> {
+        let len = inner.size()?.saturating_sub(offset);
+        Ok(SubDevice { inner, offset, len })
+    }
+
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    /// Offset of `offset..offset + len` in the inner device.
+    fn map(&self, offset: u64, len: usize) -> io::Result<u64> {
+        match offset.checked_add(len as u64) {
+            Some(end) if end <= self.len => self.offset.checked_add(offset).ok_or_else(past_end),
+            _ => Err(past_end()),
+        }
+    }
+}
+
+impl<D: BlockDevice> BlockDevice for SubDevice<D> {
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        let offset = self.map(offset, buf.len())?;
+        self.inner.read_at(offset, buf)
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
+        let offset = self.map(offset, buf.len())?;
+        self.inner.write_at(offset, buf)
+    }
+
+    fn size(&mut self) -> io::Result<u64> {
+        Ok(self.len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image() -> Vec<u8> {
+        (0..=255).collect()
+    }
+
+    #[test]
+    fn vec_reads_and_grows() {
+        let mut dev = image();
+        let mut buf = [0u8; 4];
+        dev.read_at(252, &mut buf).unwrap();
+        assert_eq!(buf, [252, 253, 254, 255]);
+
+        let err = dev.read_at(253, &mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(dev.read_at(u64::MAX, &mut buf).is_err());
+
+        dev.write_at(258, &[1, 2]).unwrap();
+        assert_eq!(dev.size().unwrap(), 260);
+        assert_eq!(dev[255..], [255, 0, 0, 1, 2]);
+    }
+
+    #[test]
+    fn sub_device_maps_offsets() {
+        let mut sub = SubDevice::new(image(), 16, 32);
+        assert_eq!(sub.size().unwrap(), 32);
+
+        let mut buf = [0u8; 4];
+        sub.read_at(0, &mut buf).unwrap();
+        assert_eq!(buf, [16, 17, 18, 19]);
+        sub.read_at(28, &mut buf).unwrap();
+        assert_eq!(buf, [44, 45, 46, 47]);
+
+        sub.write_at(4, &[0xAA]).unwrap();
+        assert_eq!(sub.into_inner()[20], 0xAA);
+    }
+
+    #[test]
+    fn sub_device_rejects_access_past_its_end() {
+        let mut sub = SubDevice::new(image(), 16, 32);
+        let mut buf = [0u8; 4];
+        for offset in [29, 32, u64::MAX - 1] {
+            let err = sub.read_at(offset, &mut buf).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        }
+        assert!(sub.write_at(31, &[0; 2]).is_err());
+        assert_eq!(sub.into_inner(), image());
+
+        let mut far = SubDevice::new(image(), u64::MAX, 8);
+        assert!(far.read_at(4, &mut buf).is_err());
+    }
+
+    #[test]
+    fn sub_device_from_offset_spans_the_rest() {
+        let mut sub = SubDevice::from_offset(image(), 200).unwrap();
+        assert_eq!(sub.size().unwrap(), 56);
+        let mut buf = [0u8; 1];
+        sub.read_at(55, &mut buf).unwrap();
+        assert_eq!(buf, [255]);
+
+        let mut beyond = SubDevice::from_offset(image(), 300).unwrap();
+        assert_eq!(beyond.size().unwrap(), 0);
+        assert!(beyond.read_at(0, &mut buf).is_err());
+    }
+}
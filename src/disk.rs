@@ -1,198 +1,1891 @@
-use std::io::{Read, Seek};
+use std::io::Read;
 
+use crate::cache::{Block, BlockCache, CacheStats, DEFAULT_CACHE_BYTES};
+use crate::device::BlockDevice;
+use crate::error::{Ext4Error, Result};
 use crate::ext4;
 use crate::ext4::LoadAble;
+use crate::file::FileHandle;
 
-pub struct Disk {
-    file: std::fs::File,
+/// Inode number of the root directory.
+pub const ROOT_INODE: u32 = 2;
+
+/// Maximum number of symlinks followed while resolving one path, as in Linux.
+const MAX_SYMLINKS: u32 = 40;
+
+/// Byte offset of the primary superblock.
+const SUPERBLOCK_OFFSET: u64 = 0x400;
+
+/// Magic number identifying an ext2/3/4 superblock.
+const EXT4_SUPER_MAGIC: u16 = 0xEF53;
+
+/// Target of a symbolic link.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkTarget {
+    Plain(Vec<u8>),
+    /// Ciphertext of an fscrypt-encrypted target, as stored on disk.
+    Encrypted(Vec<u8>),
+}
+
+/// A backup copy of the superblock, as `e2fsck -b` would be pointed at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackupSuperBlock {
+    pub group: u32,
+    /// Block holding the copy, in blocks of `block_size` bytes.
+    pub block: u64,
+    pub block_size: u32,
+    /// Last write time recorded in the copy, or 0 if it could not be read.
+    pub write_time: u64,
+    /// Whether the copy passes its checks and describes the same
+    /// filesystem as the others. Its group descriptors are not checked.
+    pub intact: bool,
+}
+
+pub struct Disk<D = std::fs::File> {
+    device: D,
+    /// Group whose superblock copy is in use; 0 for the primary.
+    sb_group: u32,
     pub super_block: ext4::structs::SuperBlock,
     pub block_size: u32,
     pub groups_per_flex: u16,
+    pub group_descs: ext4::structs::GroupDescTable,
+    cache: BlockCache,
 }
 
 impl Disk {
-    pub fn new(path: &str) -> Self {
-        let mut f = std::fs::File::open(path).expect("Failed to open file.");
+    pub fn new(path: &str) -> Result<Self> {
+        Disk::from_device(std::fs::File::open(path)?)
+    }
+}
+
+impl<D: BlockDevice> Disk<D> {
+    /// Opens the filesystem stored on `device`. If the primary superblock
+    /// or the group descriptors it leads to are damaged, the most recently
+    /// written backup whose superblock and group descriptors check out is
+    /// used instead. A primary whose only fault is its checksum is still
+    /// used when no backup is consistent.
+    pub fn from_device(mut device: D) -> Result<Self> {
+        let primary = match Self::load_super_block(&mut device, SUPERBLOCK_OFFSET) {
+            Ok((sb, true)) => match Self::try_with_super_block(device, sb, 0) {
+                Ok(disk) => return Ok(disk),
+                Err((returned, e)) => {
+                    device = returned;
+                    Err(e)
+                }
+            },
+            Ok((sb, false)) => Ok(sb),
+            Err(e @ (Ext4Error::Io(_) | Ext4Error::Unsupported(_))) => return Err(e),
+            Err(e) => Err(e),
+        };
+
+        let mut backups = Self::backup_super_blocks(&mut device)?;
+        backups.retain(|b| b.intact);
+        backups.sort_by_key(|b| (std::cmp::Reverse(b.write_time), b.group));
+        for b in backups {
+            if Self::backup_is_consistent(&mut device, &b) {
+                return Self::from_backup(device, b.block, b.block_size);
+            }
+        }
+        Self::with_super_block(device, primary?, 0)
+    }
+
+    /// Opens the filesystem on `device` from the backup superblock in block
+    /// `block` of `block_size` bytes, like `e2fsck -b block -B block_size`.
+    /// Group descriptors are read from the backup copy that goes with it.
+    pub fn from_backup(mut device: D, block: u64, block_size: u32) -> Result<Self> {
+        let offset = block
+            .checked_mul(block_size as u64)
+            .ok_or_else(|| Ext4Error::corrupt(block, "backup superblock out of range"))?;
+        let (sb, csum_ok) = Self::load_super_block(&mut device, offset)?;
+        if !csum_ok {
+            return Err(Ext4Error::corrupt(block, "superblock checksum mismatch"));
+        }
+        if 1024_u64 << sb.s_log_block_size != block_size as u64 {
+            return Err(Ext4Error::corrupt(
+                block,
+                format!(
+                    "superblock records {}-byte blocks, not {}",
+                    1024_u64 << sb.s_log_block_size,
+                    block_size
+                ),
+            ));
+        }
+        let rel = block.wrapping_sub(sb.s_first_data_block as u64);
+        let bpg = sb.s_blocks_per_group as u64;
+        let group = rel / bpg;
+        if block < sb.s_first_data_block as u64
+            || !rel.is_multiple_of(bpg)
+            || group == 0
+            || group >= sb.group_count() as u64
+            || !sb.group_has_super(group as u32)
+        {
+            return Err(Ext4Error::corrupt(
+                block,
+                "not the first block of a group holding a backup superblock",
+            ));
+        }
+        Self::with_super_block(device, sb, group as u32)
+    }
+
+    /// Backup superblocks of the filesystem on `device`, in group order.
+    /// Like `e2fsck`, the copy in group 1 is looked for at every block size
+    /// assuming the default of 8 * block size blocks per group, and the
+    /// geometry it records locates the rest. Copies past the end of the
+    /// device are left out.
+    pub fn backup_super_blocks(device: &mut D) -> Result<Vec<BackupSuperBlock>> {
+        let size = device.size()?;
+        let mut found = None;
+        for log in 0..=6 {
+            let bs = 1024_u64 << log;
+            let block = 8 * bs + (log == 0) as u64;
+            if block * bs + 1024 > size {
+                break;
+            }
+            if let Ok((sb, _)) = Self::load_super_block(device, block * bs) {
+                if sb.s_log_block_size == log
+                    && sb.s_first_data_block as u64 + sb.s_blocks_per_group as u64 == block
+                {
+                    found = Some(sb);
+                    break;
+                }
+            }
+        }
+        let Some(geometry) = found else {
+            return Ok(Vec::new());
+        };
+
+        let block_size = 1024_u32 << geometry.s_log_block_size;
+        let mut backups = Vec::new();
+        for group in geometry.super_block_groups().into_iter().skip(1) {
+            let block = group as u64 * geometry.s_blocks_per_group as u64
+                + geometry.s_first_data_block as u64;
+            let offset = block * block_size as u64;
+            if offset + 1024 > size {
+                continue;
+            }
+            let (write_time, intact) = match Self::load_super_block(device, offset) {
+                Ok((sb, csum_ok)) => (
+                    sb.write_time(),
+                    csum_ok && crate::carve::same_filesystem(&geometry, &sb),
+                ),
+                Err(_) => (0, false),
+            };
+            backups.push(BackupSuperBlock {
+                group,
+                block,
+                block_size,
+                write_time,
+                intact,
+            });
+        }
+        Ok(backups)
+    }
+
+    /// Whether `backup` and the group descriptors stored after it pass
+    /// their checks.
+    fn backup_is_consistent(device: &mut D, backup: &BackupSuperBlock) -> bool {
+        let Ok(mut disk) = Disk::from_backup(&mut *device, backup.block, backup.block_size) else {
+            return false;
+        };
+        matches!(disk.verify_super_block(), Ok(None))
+            && matches!(disk.verify_group_descs(), Ok(found) if found.is_empty())
+    }
+
+    /// Reads the superblock copy at byte `offset` and rejects it if unusable,
+    /// including when the filesystem would not fit on the device. The flag
+    /// tells whether its checksum matches, so a copy whose only fault is the
+    /// checksum can still be fallen back on.
+    fn load_super_block(device: &mut D, offset: u64) -> Result<(ext4::structs::SuperBlock, bool)> {
+        let mut raw = vec![0u8; 1024];
+        device.read_at(offset, &mut raw)?;
+        let sb = ext4::structs::SuperBlock::from_buffer(&raw, 0)?;
+        check_super_block(&sb)?;
+        let block_size = 1024_u64 << sb.s_log_block_size;
+        let device_size = device.size()?;
+        if sb
+            .blocks_count()
+            .checked_mul(block_size)
+            .is_none_or(|bytes| bytes > device_size)
+        {
+            return Err(Ext4Error::corrupt(
+                offset / block_size,
+                format!(
+                    "{} blocks do not fit on a {}-byte device",
+                    sb.blocks_count(),
+                    device_size
+                ),
+            ));
+        }
+        let csum_ok = !sb.has_metadata_csum_feature()
+            || sb.s_checksum == ext4::checksum::super_block_csum(&raw);
+        Ok((sb, csum_ok))
+    }
+
+    fn with_super_block(device: D, sb: ext4::structs::SuperBlock, sb_group: u32) -> Result<Self> {
+        Self::try_with_super_block(device, sb, sb_group).map_err(|(_, e)| e)
+    }
 
-        let sb = ext4::structs::SuperBlock::from_file_offset(&mut f, 0x400)
-            .expect("Failed to read superblock");
-        let bs: u32 = (2 as u32).pow(10 + sb.s_log_block_size);
-        let gpf: u16 = (1 as u16) << sb.s_log_groups_per_flex;
+    /// Like `with_super_block`, but hands the device back if the group
+    /// descriptors cannot be loaded, so another copy can be tried.
+    fn try_with_super_block(
+        device: D,
+        sb: ext4::structs::SuperBlock,
+        sb_group: u32,
+    ) -> std::result::Result<Self, (D, Ext4Error)> {
+        let bs: u32 = 2_u32.pow(10 + sb.s_log_block_size);
+        let gpf: u16 = 1_u16
+            .checked_shl(sb.s_log_groups_per_flex as u32)
+            .unwrap_or(0);
 
-        Disk {
-            file: f,
+        let mut d = Disk {
+            device,
+            sb_group,
             super_block: sb,
             block_size: bs,
             groups_per_flex: gpf,
+            group_descs: ext4::structs::GroupDescTable::default(),
+            cache: BlockCache::new(DEFAULT_CACHE_BYTES),
+        };
+        match d.read_group_desc_table() {
+            Ok(table) => {
+                d.group_descs = table;
+                Ok(d)
+            }
+            Err(e) => Err((d.device, e)),
         }
     }
 
-    pub fn read_block(&mut self, block_num: u64) -> std::io::Result<Vec<u8>> {
+    /// Group whose superblock copy the filesystem was opened from; 0 for
+    /// the primary.
+    pub fn super_block_group(&self) -> u32 {
+        self.sb_group
+    }
+
+    /// Byte offset of the superblock copy in use.
+    fn super_block_offset(&self) -> u64 {
+        if self.sb_group == 0 {
+            SUPERBLOCK_OFFSET
+        } else {
+            self.group_first_block(self.sb_group) * self.block_size as u64
+        }
+    }
+
+    /// Reads block `block_num`, going through the block cache.
+    pub fn read_block(&mut self, block_num: u64) -> Result<Block> {
+        if block_num >= self.super_block.blocks_count() {
+            return Err(Ext4Error::corrupt(
+                block_num,
+                "block beyond the end of the filesystem",
+            ));
+        }
+        if let Some(block) = self.cache.get(block_num) {
+            return Ok(block);
+        }
         let mut buf = vec![0u8; self.block_size as usize];
-        self.file
-            .seek(std::io::SeekFrom::Start(block_num * self.block_size as u64))?;
-        self.file.read_exact(&mut buf)?;
-        Ok(buf)
-    }
-
-    pub fn get_itable_blk_num(&mut self, group_num: u32) -> u64 {
-        let offset_in_flex = group_num as u32 % self.groups_per_flex as u32;
-        let has_redundant_copy = self.block_group_has_redundant_copy(group_num);
-        let blk_no: u64;
-        if has_redundant_copy {
-            let gd_blk_no = group_num as u64 * self.super_block.s_blocks_per_group as u64
-                + 1 // skip superblock
-                + self.super_block.s_first_data_block as u64;
-            let blk = self.read_block(gd_blk_no).unwrap();
-            let gd = ext4::structs::GroupDesc::from_buffer(&blk, 0);
-            blk_no = ((gd.bg_inode_table_hi as u64) << 32) | gd.bg_inode_table_lo as u64;
+        self.device
+            .read_at(block_num * self.block_size as u64, &mut buf)?;
+        let block = Block::from(buf);
+        self.cache.insert(block_num, block.clone());
+        Ok(block)
+    }
+
+    /// Sets the byte budget of the block cache; 0 turns caching off.
+    pub fn set_cache_capacity(&mut self, bytes: usize) {
+        self.cache.set_capacity(bytes);
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    pub fn get_itable_blk_num(&mut self, group_num: u32) -> Result<u64> {
+        match self.group_descs.get(group_num) {
+            Some(desc) => Ok(desc.inode_table()),
+            None => Err(Ext4Error::NotFound(format!("block group {}", group_num))),
+        }
+    }
+
+    /// Loads every group descriptor, one descriptor block at a time so that
+    /// META_BG blocks scattered across the disk are picked up.
+    fn read_group_desc_table(&mut self) -> Result<ext4::structs::GroupDescTable> {
+        let desc_size = self.super_block.desc_size();
+        let count = self.super_block.group_count();
+        let table_blocks = count.div_ceil(self.descs_per_block());
+
+        let mut buf = Vec::new();
+        for i in 0..table_blocks {
+            let blk = self.group_desc_block_in_use(i);
+            buf.extend_from_slice(&self.read_block(blk)?);
+        }
+        let table = ext4::structs::GroupDescTable::from_buffer(&buf, desc_size, count)?;
+        self.check_group_descs(&table)?;
+        Ok(table)
+    }
+
+    /// Rejects descriptors whose bitmaps or inode table lie past the end of
+    /// the filesystem, so block arithmetic on them cannot overflow later.
+    fn check_group_descs(&self, table: &ext4::structs::GroupDescTable) -> Result<()> {
+        let blocks = self.super_block.blocks_count();
+        let itable_blocks = self.inode_table_blocks();
+        for (group, desc) in table.descs.iter().enumerate() {
+            let itable_end = desc.inode_table().checked_add(itable_blocks);
+            let bad = if desc.block_bitmap() >= blocks {
+                "block bitmap"
+            } else if desc.inode_bitmap() >= blocks {
+                "inode bitmap"
+            } else if !matches!(itable_end, Some(end) if end <= blocks) {
+                "inode table"
+            } else {
+                continue;
+            };
+            let desc_block = self.group_desc_block_in_use(group as u32 / self.descs_per_block());
+            return Err(Ext4Error::corrupt(
+                desc_block,
+                format!(
+                    "{} of group {} beyond the end of the filesystem",
+                    bad, group
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Number of blocks in the inode table of a group.
+    fn inode_table_blocks(&self) -> u64 {
+        (self.super_block.s_inodes_per_group as u64 * self.super_block.inode_size() as u64)
+            .div_ceil(self.block_size as u64)
+    }
+
+    /// Number of group descriptors that fit in one block.
+    pub fn descs_per_block(&self) -> u32 {
+        self.block_size / self.super_block.desc_size() as u32
+    }
+
+    /// First block of block group `bg_num`.
+    pub fn group_first_block(&self, bg_num: u32) -> u64 {
+        bg_num as u64 * self.super_block.s_blocks_per_group as u64
+            + self.super_block.s_first_data_block as u64
+    }
+
+    fn has_meta_bg_feature(&self) -> bool {
+        self.super_block
+            .s_feature_incompat
+            .contains(ext4::flags::superblock::IncompatibleFeatures::META_BG)
+    }
+
+    /// Location of the primary copy of the `nr`th group descriptor block.
+    /// Without META_BG the blocks follow the block holding the primary
+    /// superblock, which is block 1 for 1KiB blocks even when bigalloc makes
+    /// s_first_data_block 0. With META_BG,
+    /// blocks from s_first_meta_bg onward sit at the start of the first group
+    /// of the meta group they describe.
+    pub fn group_desc_block(&self, nr: u32) -> u64 {
+        if !self.has_meta_bg_feature() || nr < self.super_block.s_first_meta_bg {
+            return SUPERBLOCK_OFFSET / self.block_size as u64 + 1 + nr as u64;
+        }
+        let bg = nr * self.descs_per_block();
+        self.group_first_block(bg) + self.block_group_has_redundant_copy(bg) as u64
+    }
+
+    /// Location of the copy of the `nr`th group descriptor block that goes
+    /// with the superblock copy in use. A backup superblock is followed by
+    /// its own copy of the table; META_BG blocks are taken, as e2fsprogs
+    /// does, from the second group of their meta group.
+    fn group_desc_block_in_use(&self, nr: u32) -> u64 {
+        if self.sb_group == 0 {
+            return self.group_desc_block(nr);
+        }
+        if !self.has_meta_bg_feature() || nr < self.super_block.s_first_meta_bg {
+            return self.group_first_block(self.sb_group) + 1 + nr as u64;
+        }
+        let bg = nr * self.descs_per_block() + 1;
+        if bg < self.super_block.group_count() {
+            self.group_first_block(bg) + self.block_group_has_redundant_copy(bg) as u64
         } else {
-            let is_primary_bg_in_flex = offset_in_flex == 0;
-            if is_primary_bg_in_flex {
-                blk_no = (1 + 1) * self.groups_per_flex as u64
-                    + self.super_block.s_first_data_block as u64;
+            self.group_desc_block(nr)
+        }
+    }
+
+    /// Block groups other than group 0 holding a backup superblock.
+    pub fn superblock_backup_groups(&self) -> Vec<u32> {
+        self.super_block.super_block_groups()[1..].to_vec()
+    }
+
+    /// Locations of the backup copies of the `nr`th group descriptor block.
+    pub fn group_desc_backup_blocks(&self, nr: u32) -> Vec<u64> {
+        if !self.has_meta_bg_feature() || nr < self.super_block.s_first_meta_bg {
+            return self
+                .superblock_backup_groups()
+                .into_iter()
+                .map(|bg| self.group_first_block(bg) + 1 + nr as u64)
+                .collect();
+        }
+        let first = nr * self.descs_per_block();
+        let last = first + self.descs_per_block() - 1;
+        [first + 1, last]
+            .into_iter()
+            .filter(|&bg| bg < self.super_block.group_count())
+            .map(|bg| self.group_first_block(bg) + self.block_group_has_redundant_copy(bg) as u64)
+            .collect()
+    }
+
+    /// Number of group descriptor blocks (excluding reserved GDT blocks)
+    /// stored in block group `bg_num`.
+    pub fn group_desc_blocks_in_group(&self, bg_num: u32) -> u32 {
+        let dpb = self.descs_per_block();
+        let first_meta_bg = self.super_block.s_first_meta_bg;
+        if !self.has_meta_bg_feature() || bg_num / dpb < first_meta_bg {
+            if !self.block_group_has_redundant_copy(bg_num) {
+                0
+            } else if self.has_meta_bg_feature() {
+                first_meta_bg
             } else {
-                let primary_flex_grp = group_num - offset_in_flex;
-                if self.block_group_has_redundant_copy(primary_flex_grp) {
-                    let gd_blk_no = primary_flex_grp as u64 * self.super_block.s_blocks_per_group as u64
-                        + 1 // skip superblock
-                        + self.super_block.s_first_data_block as u64;
-                    let blk = self.read_block(gd_blk_no).unwrap();
-                    let gd = ext4::structs::GroupDesc::from_buffer(&blk, 0);
-                    blk_no = ((gd.bg_inode_table_hi as u64) << 32) | gd.bg_inode_table_lo as u64;
-                } else {
-                    blk_no = (1 + 1) * self.groups_per_flex as u64
-                        + self.super_block.s_first_data_block as u64;
-                }
+                self.super_block.group_count().div_ceil(dpb)
             }
+        } else {
+            // A meta group keeps its descriptor block in its first, second and last group.
+            let first = bg_num / dpb * dpb;
+            let last = first + dpb - 1;
+            (bg_num == first || bg_num == first + 1 || bg_num == last) as u32
         }
-        group_num as u64 * self.super_block.s_blocks_per_group as u64
-            + blk_no
-            + offset_in_flex as u64 * self.super_block.s_inodes_per_group as u64
     }
 
-    pub fn block_group_has_redundant_copy(&self, bg_num: u32) -> bool {
-        if bg_num == 0 {
-            true
-        } else if self
-            .super_block
-            .s_feature_compat
-            .contains(ext4::flags::superblock::CompatibleFeatures::SPARSE_SUPER2)
+    /// Number of blocks at the start of block group `bg_num` taken by the
+    /// superblock copy, group descriptors and reserved GDT blocks.
+    pub fn group_base_meta_blocks(&self, bg_num: u32) -> u32 {
+        let has_super = self.block_group_has_redundant_copy(bg_num) as u32;
+        let gdt_blocks = self.group_desc_blocks_in_group(bg_num);
+        let dpb = self.descs_per_block();
+        if has_super == 1
+            && (!self.has_meta_bg_feature() || bg_num / dpb < self.super_block.s_first_meta_bg)
         {
-            unimplemented!("Check s_backup_bgs")
-        } else if bg_num <= 1 || !self.super_block.has_sparse_super_feature() {
-            true
-        } else if bg_num & 0x1 == 0 {
-            false
+            has_super + gdt_blocks + self.super_block.s_reserved_gdt_blocks as u32
         } else {
-            fn test_root(mut a: u32, b: u32) -> bool {
-                loop {
-                    if a < b {
-                        return false;
-                    } else if a == b {
-                        return true;
-                    } else if (a % b) != 0 {
-                        return false;
-                    }
-                    a = a / b;
-                }
-            }
+            has_super + gdt_blocks
+        }
+    }
+
+    pub fn block_group_has_redundant_copy(&self, bg_num: u32) -> bool {
+        self.super_block.group_has_super(bg_num)
+    }
+
+    /// log2 of the number of blocks per cluster; 0 without bigalloc.
+    fn cluster_bits(&self) -> u32 {
+        self.super_block
+            .s_log_cluster_size
+            .saturating_sub(self.super_block.s_log_block_size)
+    }
+
+    /// Number of clusters in block group `bg_num`. Only the last group can
+    /// be short.
+    pub fn group_cluster_count(&self, bg_num: u32) -> u32 {
+        let first = self.group_first_block(bg_num);
+        let blocks = self.super_block.blocks_count().saturating_sub(first);
+        let clusters = blocks.div_ceil(1 << self.cluster_bits());
+        clusters.min(self.super_block.s_clusters_per_group as u64) as u32
+    }
 
-            test_root(bg_num, 3) || test_root(bg_num, 5) || test_root(bg_num, 7)
+    fn group_desc(&self, bg_num: u32) -> Result<&ext4::structs::GroupDesc> {
+        self.group_descs
+            .get(bg_num)
+            .ok_or_else(|| Ext4Error::NotFound(format!("block group {}", bg_num)))
+    }
+
+    /// Block allocation bitmap of group `bg_num`, one bit per cluster. For a
+    /// BLOCK_UNINIT group it is built the way the kernel does: only the
+    /// group's own superblock copy, descriptors, bitmaps and inode table are
+    /// in use. Like the kernel, the flag is ignored unless group descriptors
+    /// are checksummed.
+    pub fn read_block_bitmap(&mut self, bg_num: u32) -> Result<ext4::structs::Bitmap> {
+        let desc = self.group_desc(bg_num)?;
+        let len = self.group_cluster_count(bg_num);
+        if !self.super_block.has_group_desc_csum()
+            || !desc
+                .bg_flags
+                .contains(ext4::flags::group_desc::GroupFlags::BLOCK_UNINIT)
+        {
+            let blk = desc.block_bitmap();
+            return Ok(ext4::structs::Bitmap::new(
+                self.read_block(blk)?.to_vec(),
+                len,
+            ));
+        }
+
+        let mut bitmap = ext4::structs::Bitmap::empty(len);
+        let shift = self.cluster_bits();
+        let base = self.group_base_meta_blocks(bg_num) as u64;
+        for cluster in 0..base.div_ceil(1 << shift) {
+            bitmap.set(cluster as u32);
+        }
+
+        // With flex_bg these may live in another group, and are then not ours.
+        let itable_blocks = self.inode_table_blocks();
+        let first = self.group_first_block(bg_num);
+        let end = first + ((len as u64) << shift);
+        let meta = [desc.block_bitmap(), desc.inode_bitmap()]
+            .into_iter()
+            .chain(desc.inode_table()..desc.inode_table() + itable_blocks);
+        for blk in meta.filter(|blk| (first..end).contains(blk)) {
+            bitmap.set(((blk - first) >> shift) as u32);
+        }
+        Ok(bitmap)
+    }
+
+    /// Inode allocation bitmap of group `bg_num`. An INODE_UNINIT group has
+    /// no inodes in use, if group descriptors are checksummed.
+    pub fn read_inode_bitmap(&mut self, bg_num: u32) -> Result<ext4::structs::Bitmap> {
+        let desc = self.group_desc(bg_num)?;
+        let len = self.super_block.s_inodes_per_group;
+        if self.super_block.has_group_desc_csum()
+            && desc
+                .bg_flags
+                .contains(ext4::flags::group_desc::GroupFlags::INODE_UNINIT)
+        {
+            return Ok(ext4::structs::Bitmap::empty(len));
+        }
+        let blk = desc.inode_bitmap();
+        Ok(ext4::structs::Bitmap::new(
+            self.read_block(blk)?.to_vec(),
+            len,
+        ))
+    }
+
+    /// Whether block `block_num` is allocated. Blocks before
+    /// s_first_data_block belong to no group and count as in use.
+    pub fn is_block_used(&mut self, block_num: u64) -> Result<bool> {
+        if block_num >= self.super_block.blocks_count() {
+            return Err(Ext4Error::NotFound(format!("block {}", block_num)));
+        }
+        let first_data_block = self.super_block.s_first_data_block as u64;
+        if block_num < first_data_block {
+            return Ok(true);
         }
+        let rel = block_num - first_data_block;
+        let blocks_per_group = self.super_block.s_blocks_per_group as u64;
+        let bg_num = (rel / blocks_per_group) as u32;
+        let bit = ((rel % blocks_per_group) >> self.cluster_bits()) as u32;
+        Ok(self.read_block_bitmap(bg_num)?.is_set(bit))
+    }
+
+    /// Whether inode `inode_num` is allocated.
+    pub fn is_inode_used(&mut self, inode_num: u32) -> Result<bool> {
+        if inode_num == 0 || inode_num > self.super_block.s_inodes_count {
+            return Err(Ext4Error::NotFound(format!("inode {}", inode_num)));
+        }
+        let inodes_per_group = self.super_block.s_inodes_per_group;
+        let bitmap = self.read_inode_bitmap((inode_num - 1) / inodes_per_group)?;
+        Ok(bitmap.is_set((inode_num - 1) % inodes_per_group))
+    }
+
+    /// Runs of allocated blocks in group `bg_num`, as absolute block numbers.
+    pub fn used_block_ranges(&mut self, bg_num: u32) -> Result<Vec<std::ops::Range<u64>>> {
+        self.block_ranges(bg_num, true)
+    }
+
+    /// Runs of free blocks in group `bg_num`, as absolute block numbers.
+    pub fn free_block_ranges(&mut self, bg_num: u32) -> Result<Vec<std::ops::Range<u64>>> {
+        self.block_ranges(bg_num, false)
+    }
+
+    fn block_ranges(&mut self, bg_num: u32, used: bool) -> Result<Vec<std::ops::Range<u64>>> {
+        let bitmap = self.read_block_bitmap(bg_num)?;
+        let first = self.group_first_block(bg_num);
+        let shift = self.cluster_bits();
+        let blocks_count = self.super_block.blocks_count();
+        Ok(bitmap
+            .ranges(used)
+            .map(|r| {
+                // The last cluster of the filesystem may be partial.
+                let end = (first + ((r.end as u64) << shift)).min(blocks_count);
+                first + ((r.start as u64) << shift)..end
+            })
+            .collect())
+    }
+
+    /// Runs of allocated inodes in group `bg_num`, as inode numbers.
+    pub fn used_inode_ranges(&mut self, bg_num: u32) -> Result<Vec<std::ops::Range<u32>>> {
+        self.inode_ranges(bg_num, true)
     }
 
-    fn get_inode(&mut self, inode_num: u32) -> ext4::structs::Inode {
+    /// Runs of free inodes in group `bg_num`, as inode numbers.
+    pub fn free_inode_ranges(&mut self, bg_num: u32) -> Result<Vec<std::ops::Range<u32>>> {
+        self.inode_ranges(bg_num, false)
+    }
+
+    fn inode_ranges(&mut self, bg_num: u32, used: bool) -> Result<Vec<std::ops::Range<u32>>> {
+        let bitmap = self.read_inode_bitmap(bg_num)?;
+        let first = bg_num * self.super_block.s_inodes_per_group + 1;
+        Ok(bitmap
+            .ranges(used)
+            .map(|r| first + r.start..first + r.end)
+            .collect())
+    }
+
+    /// Byte offset of inode `inode_num` on disk.
+    fn inode_address(&mut self, inode_num: u32) -> Result<u64> {
+        if inode_num == 0 || inode_num > self.super_block.s_inodes_count {
+            return Err(Ext4Error::NotFound(format!("inode {}", inode_num)));
+        }
         let inode_group_num = (inode_num - 1) / self.super_block.s_inodes_per_group;
-        let inode_table_blk_num = self.get_itable_blk_num(inode_group_num);
+        let inode_table_blk_num = self.get_itable_blk_num(inode_group_num)?;
 
         let inode_index_in_table = (inode_num - 1) % self.super_block.s_inodes_per_group;
         // This is not in blocks as s_inode_size is in bytes
-        let inode_offset_in_table = inode_index_in_table * self.super_block.s_inode_size as u32;
+        let inode_offset_in_table =
+            inode_index_in_table as u64 * self.super_block.inode_size() as u64;
+
+        inode_table_blk_num
+            .checked_mul(self.block_size as u64)
+            .and_then(|start| start.checked_add(inode_offset_in_table))
+            .ok_or_else(|| {
+                Ext4Error::corrupt(
+                    inode_table_blk_num,
+                    format!("inode table of group {} out of range", inode_group_num),
+                )
+            })
+    }
+
+    /// Loads inode `inode_num`. Fields beyond what the on-disk record holds
+    /// (s_inode_size and i_extra_isize) read as zero.
+    pub fn get_inode(&mut self, inode_num: u32) -> Result<ext4::structs::Inode> {
+        let raw = self.read_inode_bytes(inode_num)?;
+        ext4::structs::Inode::from_raw(&raw)
+    }
+
+    /// The full on-disk record of inode `inode_num`, s_inode_size bytes long.
+    fn read_inode_bytes(&mut self, inode_num: u32) -> Result<Vec<u8>> {
+        let inode_address = self.inode_address(inode_num)?;
+        let block_size = self.block_size as u64;
+        // Records never straddle blocks, as the inode size divides the block size.
+        let block = self.read_block(inode_address / block_size)?;
+        let offset = (inode_address % block_size) as usize;
+        Ok(block[offset..offset + self.super_block.inode_size()].to_vec())
+    }
+
+    /// The in-inode space left after the extra fields, which holds extended
+    /// attributes. Empty for 128-byte inodes.
+    pub fn inode_xattr_space(&mut self, inode_num: u32) -> Result<Vec<u8>> {
+        let raw = self.read_inode_bytes(inode_num)?;
+        let inode = ext4::structs::Inode::from_raw(&raw)?;
+        Ok(raw[inode.xattr_area_offset().min(raw.len())..].to_vec())
+    }
 
-        let inode_address =
-            inode_table_blk_num * self.block_size as u64 + inode_offset_in_table as u64;
+    /// Extended attributes stored in the inode, after its extra fields.
+    fn in_inode_xattrs(&mut self, inode_num: u32) -> Result<Vec<ext4::structs::xattr::Xattr>> {
+        let space = self.inode_xattr_space(inode_num)?;
+        if space.len() < 4 || read_u32(&space, 0) != ext4::structs::xattr::MAGIC {
+            return Ok(Vec::new());
+        }
+
+        // Value offsets are relative to the first entry.
+        let area = &space[4..];
+        self.parse_xattr_entries(inode_num, area, 0, area)
+    }
 
-        ext4::structs::Inode::from_file_offset(&mut self.file, inode_address)
-            .expect("Failed to get inode")
+    /// Location of the inode's external extended attribute block, if it has one.
+    fn xattr_block_num(inode: &ext4::structs::Inode) -> Option<u64> {
+        let blk = ((inode.l_i_file_acl_high as u64) << 32) | inode.i_file_acl_lo as u64;
+        (blk != 0).then_some(blk)
     }
 
-    pub fn read_dir(&mut self, inode_num: u32) -> Vec<ext4::structs::dir::Entry2> {
-        let inode = self.get_inode(inode_num);
+    /// Header of the inode's external extended attribute block.
+    pub fn get_xattr_block_header(
+        &mut self,
+        inode_num: u32,
+    ) -> Result<Option<ext4::structs::xattr::Header>> {
+        let inode = self.get_inode(inode_num)?;
+        let blk = match Self::xattr_block_num(&inode) {
+            Some(blk) => blk,
+            None => return Ok(None),
+        };
+        let buf = self.read_block(blk)?;
+        let header = ext4::structs::xattr::Header::from_buffer(&buf, 0)?;
+        Ok((header.h_magic == ext4::structs::xattr::MAGIC).then_some(header))
+    }
 
-        let extents = self.get_extents(&inode);
-        /* if extents.len() > 1 {
-            unimplemented!();
-        } */
+    /// Extended attributes stored in the inode's external attribute block.
+    fn block_xattrs(
+        &mut self,
+        inode_num: u32,
+        inode: &ext4::structs::Inode,
+    ) -> Result<Vec<ext4::structs::xattr::Xattr>> {
+        let blk = match Self::xattr_block_num(inode) {
+            Some(blk) => blk,
+            None => return Ok(Vec::new()),
+        };
+        let buf = self.read_block(blk)?;
+        let header = ext4::structs::xattr::Header::from_buffer(&buf, 0)?;
+        if header.h_magic != ext4::structs::xattr::MAGIC || header.h_blocks != 1 {
+            return Ok(Vec::new());
+        }
+
+        // Value offsets are relative to the start of the block.
+        let header_size = std::mem::size_of::<ext4::structs::xattr::Header>();
+        self.parse_xattr_entries(inode_num, &buf, header_size, &buf)
+    }
 
-        let e = &extents[0];
-        let blk_no: u64 = ((e.ee_start_hi as u64) << 32) | e.ee_start_lo as u64;
-        let block = self.read_block(blk_no).unwrap();
+    /// Decodes the entry list of inode `inode_num` starting at `offset` of
+    /// `area`, taking values from `value_base` or, for large values, from the
+    /// EA inode holding them.
+    fn parse_xattr_entries(
+        &mut self,
+        inode_num: u32,
+        area: &[u8],
+        mut offset: usize,
+        value_base: &[u8],
+    ) -> Result<Vec<ext4::structs::xattr::Xattr>> {
+        let entry_size = std::mem::size_of::<ext4::structs::xattr::Entry>();
+        let mut xattrs = Vec::new();
 
+        // The entry list ends with four zero bytes.
+        while offset + entry_size <= area.len() && read_u32(&area[offset..], 0) != 0 {
+            let entry = ext4::structs::xattr::Entry::from_buffer(area, offset)?;
+            let name_start = offset + entry_size;
+            let name_end = name_start + entry.e_name_len as usize;
+            if name_end > area.len() {
+                break;
+            }
+
+            let value = if entry.e_value_inum != 0 {
+                self.read_ea_inode(inode_num, entry.e_value_inum, entry.e_value_size)?
+            } else {
+                let value_start = entry.e_value_offs as usize;
+                let value_end = value_start + entry.e_value_size as usize;
+                match value_base.get(value_start..value_end) {
+                    Some(v) => v.to_vec(),
+                    None => break,
+                }
+            };
+
+            xattrs.push(ext4::structs::xattr::Xattr {
+                name_index: entry.e_name_index,
+                name: area[name_start..name_end].to_vec(),
+                value,
+            });
+            offset = name_end.next_multiple_of(4);
+        }
+        Ok(xattrs)
+    }
+
+    /// The first `size` bytes of EA inode `ea_inode_num`, which holds a value
+    /// of inode `owner`. Only its extents or block map are read, so a
+    /// reference looping back to an inline data inode cannot recurse.
+    fn read_ea_inode(&mut self, owner: u32, ea_inode_num: u32, size: u32) -> Result<Vec<u8>> {
+        let ea_inode = self.get_inode(ea_inode_num)?;
+        if ea_inode_num == owner
+            || !ea_inode
+                .i_flags
+                .contains(ext4::flags::inode::IFlags::Ext4EaInodeFl)
+            || ea_inode
+                .i_flags
+                .contains(ext4::flags::inode::IFlags::Ext4InlineDataFl)
+        {
+            return Err(Ext4Error::corrupt(
+                0,
+                format!(
+                    "xattr of inode {} refers to inode {}, which is not an EA inode",
+                    owner, ea_inode_num
+                ),
+            ));
+        }
+        let mut value = Vec::new();
+        FileHandle::new(self, &ea_inode)?
+            .take(size as u64)
+            .read_to_end(&mut value)?;
+        Ok(value)
+    }
+
+    /// All extended attributes of inode `inode_num`, in-inode ones first.
+    pub fn list_xattrs(&mut self, inode_num: u32) -> Result<Vec<ext4::structs::xattr::Xattr>> {
+        let inode = self.get_inode(inode_num)?;
+        let mut xattrs = self.in_inode_xattrs(inode_num)?;
+        xattrs.extend(self.block_xattrs(inode_num, &inode)?);
+        Ok(xattrs)
+    }
+
+    /// Value of the extended attribute with the full name `name`, such as "user.comment".
+    pub fn get_xattr(&mut self, inode_num: u32, name: &str) -> Result<Option<Vec<u8>>> {
+        let (name_index, suffix) = match ext4::structs::xattr::split_name(name) {
+            Some(split) => split,
+            None => return Ok(None),
+        };
+        Ok(self
+            .list_xattrs(inode_num)?
+            .into_iter()
+            .find(|x| x.name_index == name_index && x.name == suffix)
+            .map(|x| x.value))
+    }
+
+    /// The POSIX access ACL of inode `inode_num`, if it has one.
+    pub fn get_acl(&mut self, inode_num: u32) -> Result<Option<ext4::structs::acl::Acl>> {
+        self.acl_xattr(inode_num, "system.posix_acl_access")
+    }
+
+    /// The POSIX default ACL of directory `inode_num`, if it has one.
+    pub fn get_default_acl(&mut self, inode_num: u32) -> Result<Option<ext4::structs::acl::Acl>> {
+        self.acl_xattr(inode_num, "system.posix_acl_default")
+    }
+
+    /// Decodes the ACL in xattr `name`. One that is present but does not
+    /// decode is an error, not a missing ACL, so permissions are never
+    /// silently taken from the mode bits instead.
+    fn acl_xattr(&mut self, inode_num: u32, name: &str) -> Result<Option<ext4::structs::acl::Acl>> {
+        let Some(value) = self.get_xattr(inode_num, name)? else {
+            return Ok(None);
+        };
+        match ext4::structs::acl::Acl::from_xattr(&value) {
+            Some(acl) => Ok(Some(acl)),
+            None => Err(Ext4Error::corrupt(
+                0,
+                format!("malformed {} of inode {}", name, inode_num),
+            )),
+        }
+    }
+
+    /// Permissions a process running as `uid` with groups `gids` has on inode
+    /// `inode_num`, from its access ACL or, lacking one, its mode bits.
+    pub fn effective_perms(
+        &mut self,
+        inode_num: u32,
+        uid: u32,
+        gids: &[u32],
+    ) -> Result<ext4::flags::acl::AclPerm> {
+        let inode = self.get_inode(inode_num)?;
+        let acl = self
+            .get_acl(inode_num)?
+            .unwrap_or_else(|| ext4::structs::acl::Acl::from_mode(&inode.i_mode));
+        Ok(acl.effective_perms(inode.uid(), inode.gid(), uid, gids))
+    }
+
+    /// Value of the in-inode "system.data" attribute, which continues inline data.
+    fn inline_data_xattr(&mut self, inode_num: u32) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .in_inode_xattrs(inode_num)?
+            .into_iter()
+            .find(|x| x.name_index == 7 && x.name == b"data")
+            .map(|x| x.value))
+    }
+
+    /// Contents of an inline data inode: the 60 bytes of i_block,
+    /// continued in the "system.data" extended attribute.
+    fn inline_data(&mut self, inode_num: u32, inode: &ext4::structs::Inode) -> Result<Vec<u8>> {
+        let size = inode.size();
+        let mut data = inode.i_block.to_vec();
+        if let Some(rest) = self.inline_data_xattr(inode_num)? {
+            data.extend(rest);
+        }
+        data.truncate(size as usize);
+        Ok(data)
+    }
+
+    /// Handle over the contents of `inode`, wherever they are stored.
+    fn open_inode(
+        &mut self,
+        inode_num: u32,
+        inode: &ext4::structs::Inode,
+    ) -> Result<FileHandle<'_, D>> {
+        if inode
+            .i_flags
+            .contains(ext4::flags::inode::IFlags::Ext4InlineDataFl)
+        {
+            let data = self.inline_data(inode_num, inode)?;
+            Ok(FileHandle::from_inline(self, data))
+        } else {
+            FileHandle::new(self, inode)
+        }
+    }
+
+    /// Opens the contents of inode `inode_num` for reading.
+    pub fn open(&mut self, inode_num: u32) -> Result<FileHandle<'_, D>> {
+        let inode = self.get_inode(inode_num)?;
+        self.open_inode(inode_num, &inode)
+    }
+
+    pub fn read_dir(&mut self, inode_num: u32) -> Result<Vec<ext4::structs::dir::Entry2>> {
+        let inode = self.get_inode(inode_num)?;
+        let block_size = self.block_size as u64;
+
+        let use_htree = inode
+            .i_flags
+            .contains(ext4::flags::inode::IFlags::Ext4IndexFl);
+
+        let has_largedir = self
+            .super_block
+            .s_feature_incompat
+            .contains(ext4::flags::superblock::IncompatibleFeatures::LARGEDIR);
+
+        if inode
+            .i_flags
+            .contains(ext4::flags::inode::IFlags::Ext4InlineDataFl)
+        {
+            return self.read_inline_dir(inode_num, &inode);
+        }
+
+        let mut dir = FileHandle::new(self, &inode)?;
         let mut entries = Vec::<ext4::structs::dir::Entry2>::new();
 
+        if use_htree {
+            // The root block holds "." and ".." ahead of the dx data,
+            // with ".." spanning the rest of the block.
+            let block = dir.read_logical_block(0)?;
+            let root_blk = dir.physical_block(0).unwrap_or(0);
+            parse_dir_block(&block, root_blk, &mut entries)?;
+
+            let dx_root = ext4::structs::dir::DxRoot::from_buffer(&block, 0)?;
+            let max_levels = if has_largedir { 3 } else { 2 };
+            if dx_root.indirect_levels >= max_levels {
+                return Err(Ext4Error::corrupt(
+                    root_blk,
+                    format!("hash tree too deep: {} levels", dx_root.indirect_levels),
+                ));
+            }
+            let table_offset = 0x18 + dx_root.info_length as usize;
+            for dentry in read_dx_entries(&block, root_blk, table_offset, dx_root.count)? {
+                walk_dx_node(
+                    &mut dir,
+                    dentry.block,
+                    dx_root.indirect_levels,
+                    &mut entries,
+                )?;
+            }
+        } else {
+            let num_blocks = dir.size().div_ceil(block_size) as u32;
+            for lblk in 0..num_blocks {
+                // Holes in a directory hold no entries.
+                let pblk = match dir.physical_block(lblk) {
+                    Some(pblk) => pblk,
+                    None => continue,
+                };
+                let blk = dir.read_logical_block(lblk)?;
+                parse_dir_block(&blk, pblk, &mut entries)?;
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Lists an inline directory. Its data starts with the parent's inode
+    /// number instead of "." and ".." records, followed by entries filling the
+    /// rest of i_block and, separately, the "system.data" attribute.
+    fn read_inline_dir(
+        &mut self,
+        inode_num: u32,
+        inode: &ext4::structs::Inode,
+    ) -> Result<Vec<ext4::structs::dir::Entry2>> {
+        let parent = read_u32(&inode.i_block, 0);
+        let mut entries = vec![
+            ext4::structs::dir::Entry2::new(inode_num, b".", ext4::flags::dir::FileType::Ext4FtDir),
+            ext4::structs::dir::Entry2::new(parent, b"..", ext4::flags::dir::FileType::Ext4FtDir),
+        ];
+
+        parse_dir_block(&inode.i_block[4..], 0, &mut entries)?;
+        if let Some(rest) = self.inline_data_xattr(inode_num)? {
+            parse_dir_block(&rest, 0, &mut entries)?;
+        }
+        Ok(entries)
+    }
+
+    /// Finds the entry called `name` in directory `dir_inode`.
+    /// Hash-indexed directories are probed through the htree, so only the
+    /// leaf block the name hashes to is read; others are scanned linearly.
+    pub fn lookup(
+        &mut self,
+        dir_inode: u32,
+        name: &[u8],
+    ) -> Result<Option<ext4::structs::dir::Entry2>> {
+        let inode = self.get_inode(dir_inode)?;
         let use_htree = inode
             .i_flags
             .contains(ext4::flags::inode::IFlags::Ext4IndexFl);
 
         if use_htree {
-            let dx_root = ext4::structs::dir::DxRoot::from_buffer(&block, 0);
-            if dx_root.indirect_levels != 0 {
-                unimplemented!("Support for multi level hash tree is pending")
-            }
-            for i in 0..dx_root.count {
-                let nblock;
-                if i == 0 {
-                    nblock = blk_no + dx_root.block as u64;
-                } else {
-                    let dentry = ext4::structs::dir::DxEntry::from_buffer(
-                        &block,
-                        40 + ((i - 1) * 8) as usize,
-                    );
-                    nblock = blk_no + dentry.block as u64;
+            let seed = self.super_block.s_hash_seed;
+            let unsigned_hash = self.super_block.has_unsigned_hash();
+            let max_levels = if self
+                .super_block
+                .s_feature_incompat
+                .contains(ext4::flags::superblock::IncompatibleFeatures::LARGEDIR)
+            {
+                3
+            } else {
+                2
+            };
+
+            let mut dir = FileHandle::new(self, &inode)?;
+            let root = dir.read_logical_block(0)?;
+            let dx_root = ext4::structs::dir::DxRoot::from_buffer(&root, 0)?;
+
+            // "." and ".." live in the root block rather than in a hashed leaf.
+            if name == b"." || name == b".." {
+                let mut entries = Vec::new();
+                parse_dir_block(&root, dir.physical_block(0).unwrap_or(0), &mut entries)?;
+                return Ok(entries.into_iter().find(|e| e.name() == name));
+            }
+
+            // Signedness is a filesystem-wide flag layered over the per-directory version.
+            let mut version = dx_root.hash_version;
+            if unsigned_hash && version <= ext4::flags::superblock::DxHash::TEA.bits() {
+                version += 3;
+            }
+            let version = ext4::flags::superblock::DxHash::from_bits_retain(version);
+
+            if let Some((hash, _)) = ext4::dx_hash(name, version, &seed) {
+                return dx_lookup(&mut dir, &root, &dx_root, max_levels, hash, name);
+            }
+        }
+
+        Ok(self
+            .read_dir(dir_inode)?
+            .into_iter()
+            .find(|e| e.name() == name))
+    }
+
+    /// Resolves an absolute `path` to an inode number, following symlinks
+    /// along the way, including the final component. Errors name the part
+    /// of `path` walked so far, never the components of link targets.
+    pub fn resolve(&mut self, path: &str) -> Result<u32> {
+        // Components still to walk, each flagged if it comes from `path`.
+        let mut pending: Vec<(Vec<u8>, bool)> = split_path(path.as_bytes())
+            .into_iter()
+            .map(|c| (c, true))
+            .collect();
+        let mut cur = ROOT_INODE;
+        let mut walked = String::new();
+        let mut links_followed = 0;
+
+        while let Some((component, from_path)) = pending.pop() {
+            if component == b"." {
+                continue;
+            }
+
+            let dir = self.get_inode(cur)?;
+            if dir.file_type() != ext4::flags::dir::FileType::Ext4FtDir {
+                return Err(Ext4Error::NotADirectory(walked));
+            }
+            if from_path {
+                walked.push('/');
+                walked.push_str(&String::from_utf8_lossy(&component));
+            }
+
+            let entry = match self.lookup(cur, &component)? {
+                Some(e) => e,
+                None => return Err(Ext4Error::NotFound(walked)),
+            };
+
+            let inode = self.get_inode(entry.inode)?;
+            if inode.file_type() == ext4::flags::dir::FileType::Ext4FtSymlink {
+                links_followed += 1;
+                if links_followed > MAX_SYMLINKS {
+                    return Err(Ext4Error::TooManyLinks(path.to_string()));
                 }
-                let blk = self.read_block(nblock).unwrap();
-                let mut offset = 0;
-                while offset < blk.len() {
-                    let de = ext4::structs::dir::Entry2::from_buffer(&blk, offset);
-                    offset = offset + de.rec_len as usize;
-                    if de.inode == 0 {
-                        continue;
-                    }
-                    entries.push(de);
+                let target = match self.symlink_target(entry.inode, &inode)? {
+                    LinkTarget::Plain(target) => target,
+                    LinkTarget::Encrypted(_) => return Err(Ext4Error::Encrypted(walked)),
+                };
+                // Like Linux, an empty target names nothing.
+                if target.is_empty() {
+                    return Err(Ext4Error::NotFound(walked));
                 }
-            }
-        } else {
-            let mut offset = 0;
-            while offset < block.len() {
-                let de = ext4::structs::dir::Entry2::from_buffer(&block, offset);
-                offset = offset + de.rec_len as usize;
-                if de.inode == 0 {
-                    continue;
+                if target.first() == Some(&b'/') {
+                    cur = ROOT_INODE;
                 }
-                entries.push(de);
+                // The target is resolved relative to the directory holding the link.
+                pending.extend(split_path(&target).into_iter().map(|c| (c, false)));
+            } else {
+                cur = entry.inode;
             }
         }
 
-        return entries;
+        Ok(cur)
     }
 
-    fn get_extents(&mut self, inode: &ext4::structs::Inode) -> Vec<ext4::structs::extent::Extent> {
-        let buf = inode.i_block.to_vec();
-        let eh = ext4::structs::extent::Header::from_buffer(&buf, 0);
-        assert_eq!(eh.eh_magic, 0xf30a);
+    /// Target of symlink `inode_num`, or `None` if the inode is not a symlink.
+    pub fn read_link(&mut self, inode_num: u32) -> Result<Option<LinkTarget>> {
+        let inode = self.get_inode(inode_num)?;
+        if inode.file_type() != ext4::flags::dir::FileType::Ext4FtSymlink {
+            return Ok(None);
+        }
+        self.symlink_target(inode_num, &inode).map(Some)
+    }
+
+    /// Target of a symlink inode. Fast symlinks keep targets shorter than
+    /// i_block directly in it; slow ones store them like file contents.
+    fn symlink_target(
+        &mut self,
+        inode_num: u32,
+        inode: &ext4::structs::Inode,
+    ) -> Result<LinkTarget> {
+        let size = inode.size();
+        let is_fast = size < inode.i_block.len() as u64
+            && !inode.i_flags.intersects(
+                ext4::flags::inode::IFlags::Ext4ExtentsFl
+                    | ext4::flags::inode::IFlags::Ext4InlineDataFl,
+            );
+
+        let target = if is_fast {
+            inode.i_block[..size as usize].to_vec()
+        } else {
+            let mut target = Vec::new();
+            self.open_inode(inode_num, inode)?
+                .read_to_end(&mut target)?;
+            target
+        };
+
+        if inode
+            .i_flags
+            .contains(ext4::flags::inode::IFlags::Ext4EncryptFl)
+        {
+            Ok(LinkTarget::Encrypted(target))
+        } else {
+            Ok(LinkTarget::Plain(target))
+        }
+    }
 
+    /// Logical to physical mapping of an inode's data, in logical block order.
+    /// Block-mapped (ext2/ext3) inodes are converted to equivalent extents.
+    pub fn get_extents(
+        &mut self,
+        inode: &ext4::structs::Inode,
+    ) -> Result<Vec<ext4::structs::extent::Extent>> {
         let mut extents = Vec::<ext4::structs::extent::Extent>::new();
+        if inode
+            .i_flags
+            .contains(ext4::flags::inode::IFlags::Ext4ExtentsFl)
+        {
+            self.walk_extent_node(&inode.i_block, 0, None, &mut extents)?;
+        } else {
+            self.walk_block_map(&inode.i_block, &mut extents)?;
+        }
+        Ok(extents)
+    }
+
+    /// Collects the blocks of a legacy block map: 12 direct pointers followed by
+    /// single, double and triple indirect blocks.
+    fn walk_block_map(
+        &mut self,
+        i_block: &[u8],
+        extents: &mut Vec<ext4::structs::extent::Extent>,
+    ) -> Result<()> {
+        let ptrs_per_blk = self.block_size as u64 / 4;
+
+        for i in 0..12 {
+            push_mapped_block(extents, i as u64, read_u32(i_block, i));
+        }
+
+        let mut lblk = 12;
+        for level in 1..=3 {
+            let ptr = read_u32(i_block, 11 + level);
+            if ptr != 0 {
+                self.walk_indirect_block(ptr, level as u32, lblk, extents)?;
+            }
+            lblk += ptrs_per_blk.pow(level as u32);
+        }
+        Ok(())
+    }
+
+    /// Collects the blocks below an indirect block at `level` (1 for single
+    /// indirect) whose first entry maps logical block `first_lblk`.
+    fn walk_indirect_block(
+        &mut self,
+        blk: u32,
+        level: u32,
+        first_lblk: u64,
+        extents: &mut Vec<ext4::structs::extent::Extent>,
+    ) -> Result<()> {
+        let buf = self.read_block(blk as u64)?;
+        let ptrs_per_blk = self.block_size as usize / 4;
+        let child_span = (ptrs_per_blk as u64).pow(level - 1);
+
+        for i in 0..ptrs_per_blk {
+            let ptr = read_u32(&buf, i);
+            if ptr == 0 {
+                continue;
+            }
+            let lblk = first_lblk + i as u64 * child_span;
+            if level == 1 {
+                push_mapped_block(extents, lblk, ptr);
+            } else {
+                self.walk_indirect_block(ptr, level - 1, lblk, extents)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Collects the leaf extents below the node in `buf`, in logical block order.
+    /// `blk` is where the node was read from, 0 for the root in i_block.
+    /// `expected_depth` is the depth the parent index promised for this node, if any.
+    fn walk_extent_node(
+        &mut self,
+        buf: &[u8],
+        blk: u64,
+        expected_depth: Option<u16>,
+        extents: &mut Vec<ext4::structs::extent::Extent>,
+    ) -> Result<()> {
+        let eh = ext4::structs::extent::Header::from_buffer(buf, 0)?;
+        if eh.eh_magic != 0xf30a {
+            return Err(Ext4Error::corrupt(
+                blk,
+                format!("bad extent header magic {:#x}", eh.eh_magic),
+            ));
+        }
+        // The extent tree can be at most 5 levels deep.
+        if eh.eh_depth > 5 {
+            return Err(Ext4Error::corrupt(blk, "extent tree too deep"));
+        }
+        if expected_depth.is_some_and(|depth| depth != eh.eh_depth) {
+            return Err(Ext4Error::corrupt(blk, "extent node at unexpected depth"));
+        }
+        if (eh.eh_entries as usize + 1) * 12 > buf.len() {
+            return Err(Ext4Error::corrupt(blk, "extent node overflows its block"));
+        }
+
         if eh.eh_depth == 0 {
             for i in 0..eh.eh_entries as usize {
                 extents.push(ext4::structs::extent::Extent::from_buffer(
-                    &buf,
+                    buf,
                     (i + 1) * 12,
-                ));
+                )?);
             }
         } else {
-            unimplemented!("Internal node encountered")
+            for i in 0..eh.eh_entries as usize {
+                let idx = ext4::structs::extent::Idx::from_buffer(buf, (i + 1) * 12)?;
+                let leaf_blk = ((idx.ei_leaf_hi as u64) << 32) | idx.ei_leaf_lo as u64;
+                let node = self.read_block(leaf_blk)?;
+                self.walk_extent_node(&node, leaf_blk, Some(eh.eh_depth - 1), extents)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the superblock checksum. Returns `None` when it matches or the
+    /// filesystem has no metadata checksums.
+    pub fn verify_super_block(&mut self) -> Result<Option<ext4::checksum::ChecksumMismatch>> {
+        if !self.super_block.has_metadata_csum_feature() {
+            return Ok(None);
+        }
+        let mut raw = vec![0u8; 1024];
+        self.device.read_at(self.super_block_offset(), &mut raw)?;
+        Ok(mismatch(
+            ext4::checksum::Checksummed::SuperBlock,
+            self.super_block.s_checksum,
+            ext4::checksum::super_block_csum(&raw),
+        ))
+    }
+
+    /// The on-disk bytes of the descriptor of group `group`.
+    fn read_group_desc_raw(&mut self, group: u32) -> Result<Vec<u8>> {
+        let desc_size = self.super_block.desc_size();
+        let blk = self.group_desc_block_in_use(group / self.descs_per_block());
+        let offset = (group % self.descs_per_block()) as usize * desc_size;
+        let buf = self.read_block(blk)?;
+        Ok(buf[offset..offset + desc_size].to_vec())
+    }
+
+    /// Checks the checksum of every group descriptor: crc32c with
+    /// metadata_csum, which takes precedence, or the older crc16 of gdt_csum.
+    pub fn verify_group_descs(&mut self) -> Result<Vec<ext4::checksum::ChecksumMismatch>> {
+        let mut found = Vec::new();
+        let metadata_csum = self.super_block.has_metadata_csum_feature();
+        if !metadata_csum && !self.super_block.has_gdt_csum_feature() {
+            return Ok(found);
+        }
+        let seed = ext4::checksum::fs_seed(&self.super_block);
+        let uuid = self.super_block.s_uuid;
+        for group in 0..self.group_descs.len() as u32 {
+            let raw = self.read_group_desc_raw(group)?;
+            let stored = self.group_descs.descs[group as usize].bg_checksum;
+            let computed = if metadata_csum {
+                ext4::checksum::group_desc_csum(seed, group, &raw)
+            } else {
+                ext4::checksum::group_desc_crc16(&uuid, group, &raw)
+            };
+            found.extend(mismatch(
+                ext4::checksum::Checksummed::GroupDesc(group),
+                stored as u32,
+                computed as u32,
+            ));
+        }
+        Ok(found)
+    }
+
+    /// Checks the block and inode bitmap checksums of group `group`.
+    /// Bitmaps of uninitialized groups are not on disk and are skipped.
+    pub fn verify_bitmaps(&mut self, group: u32) -> Result<Vec<ext4::checksum::ChecksumMismatch>> {
+        let mut found = Vec::new();
+        if !self.super_block.has_metadata_csum_feature() {
+            return Ok(found);
+        }
+        let desc = match self.group_descs.get(group) {
+            Some(desc) => desc,
+            None => return Err(Ext4Error::NotFound(format!("block group {}", group))),
+        };
+        let flags = desc.bg_flags;
+        let (block_bitmap, block_csum) = (desc.block_bitmap(), desc.block_bitmap_csum());
+        let (inode_bitmap, inode_csum) = (desc.inode_bitmap(), desc.inode_bitmap_csum());
+
+        let seed = ext4::checksum::fs_seed(&self.super_block);
+        // 32-byte descriptors only have room for the low halves.
+        let mask = if self.super_block.desc_size() >= 64 {
+            u32::MAX
+        } else {
+            0xFFFF
+        };
+
+        if !flags.contains(ext4::flags::group_desc::GroupFlags::BLOCK_UNINIT) {
+            let bitmap = self.read_block(block_bitmap)?;
+            let len = self.super_block.s_clusters_per_group as usize / 8;
+            found.extend(mismatch(
+                ext4::checksum::Checksummed::BlockBitmap(group),
+                block_csum,
+                ext4::checksum::bitmap_csum(seed, &bitmap, len) & mask,
+            ));
+        }
+        if !flags.contains(ext4::flags::group_desc::GroupFlags::INODE_UNINIT) {
+            let bitmap = self.read_block(inode_bitmap)?;
+            let len = self.super_block.s_inodes_per_group as usize / 8;
+            found.extend(mismatch(
+                ext4::checksum::Checksummed::InodeBitmap(group),
+                inode_csum,
+                ext4::checksum::bitmap_csum(seed, &bitmap, len) & mask,
+            ));
+        }
+        Ok(found)
+    }
+
+    /// Checks the checksum of inode `inode_num` itself.
+    pub fn verify_inode(
+        &mut self,
+        inode_num: u32,
+    ) -> Result<Option<ext4::checksum::ChecksumMismatch>> {
+        if !self.super_block.has_metadata_csum_feature() {
+            return Ok(None);
+        }
+        let raw = self.read_inode_bytes(inode_num)?;
+        self.inode_csum_mismatch(inode_num, &raw)
+    }
+
+    fn inode_csum_mismatch(
+        &self,
+        inode_num: u32,
+        raw: &[u8],
+    ) -> Result<Option<ext4::checksum::ChecksumMismatch>> {
+        let inode = ext4::structs::Inode::from_raw(raw)?;
+        let stored = ((inode.i_checksum_hi as u32) << 16) | inode.l_i_checksum_lo as u32;
+        let seed = ext4::checksum::fs_seed(&self.super_block);
+        Ok(mismatch(
+            ext4::checksum::Checksummed::Inode(inode_num),
+            stored,
+            ext4::checksum::inode_csum(seed, inode_num, raw),
+        ))
+    }
+
+    /// Checks the checksummed blocks owned by inode `inode_num`: extent tree
+    /// blocks, directory leaves, htree nodes and its xattr block.
+    pub fn verify_inode_blocks(
+        &mut self,
+        inode_num: u32,
+    ) -> Result<Vec<ext4::checksum::ChecksumMismatch>> {
+        let mut found = Vec::new();
+        if !self.super_block.has_metadata_csum_feature() {
+            return Ok(found);
+        }
+        let inode = self.get_inode(inode_num)?;
+        let seed = ext4::checksum::fs_seed(&self.super_block);
+        let inode_seed = ext4::checksum::inode_seed(seed, inode_num, inode.i_generation);
+
+        if inode
+            .i_flags
+            .contains(ext4::flags::inode::IFlags::Ext4ExtentsFl)
+        {
+            self.verify_extent_node(&inode.i_block, inode_num, inode_seed, &mut found)?;
+        }
+        if inode.file_type() == ext4::flags::dir::FileType::Ext4FtDir
+            && !inode
+                .i_flags
+                .contains(ext4::flags::inode::IFlags::Ext4InlineDataFl)
+        {
+            self.verify_dir_blocks(inode_num, &inode, inode_seed, &mut found)?;
+        }
+        if let Some(blk) = Self::xattr_block_num(&inode) {
+            let buf = self.read_block(blk)?;
+            let header = ext4::structs::xattr::Header::from_buffer(&buf, 0)?;
+            if header.h_magic == ext4::structs::xattr::MAGIC {
+                found.extend(mismatch(
+                    ext4::checksum::Checksummed::XattrBlock { block: blk },
+                    header.h_checksum,
+                    ext4::checksum::xattr_block_csum(seed, blk, &buf),
+                ));
+            }
+        }
+        Ok(found)
+    }
+
+    /// Checks the tails of the extent blocks below the node in `buf`.
+    fn verify_extent_node(
+        &mut self,
+        buf: &[u8],
+        inode_num: u32,
+        inode_seed: u32,
+        found: &mut Vec<ext4::checksum::ChecksumMismatch>,
+    ) -> Result<()> {
+        let eh = ext4::structs::extent::Header::from_buffer(buf, 0)?;
+        if eh.eh_magic != 0xf30a || eh.eh_depth == 0 || eh.eh_depth > 5 {
+            return Ok(());
+        }
+        for i in 0..eh.eh_entries as usize {
+            let idx = ext4::structs::extent::Idx::from_buffer(buf, (i + 1) * 12)?;
+            let leaf_blk = ((idx.ei_leaf_hi as u64) << 32) | idx.ei_leaf_lo as u64;
+            let node = self.read_block(leaf_blk)?;
+            let child = ext4::structs::extent::Header::from_buffer(&node, 0)?;
+            // eh_depth is at least 1 here, and the child's may be anything.
+            if child.eh_magic != 0xf30a || child.eh_depth != eh.eh_depth - 1 {
+                continue;
+            }
+
+            // The tail follows the last slot the node has room for.
+            let tail_offset = (child.eh_max as usize + 1) * 12;
+            if tail_offset + 4 <= node.len() {
+                let tail = ext4::structs::extent::Tail::from_buffer(&node, tail_offset)?;
+                found.extend(mismatch(
+                    ext4::checksum::Checksummed::ExtentBlock {
+                        inode: inode_num,
+                        block: leaf_blk,
+                    },
+                    tail.et_checksum,
+                    ext4::checksum::extent_block_csum(inode_seed, &node, tail_offset),
+                ));
+            }
+            self.verify_extent_node(&node, inode_num, inode_seed, found)?;
+        }
+        Ok(())
+    }
+
+    /// Checks every block of a directory: the dx root and nodes of an
+    /// htree, and the tail of each leaf. Blocks without a tail are skipped.
+    fn verify_dir_blocks(
+        &mut self,
+        inode_num: u32,
+        inode: &ext4::structs::Inode,
+        inode_seed: u32,
+        found: &mut Vec<ext4::checksum::ChecksumMismatch>,
+    ) -> Result<()> {
+        let use_htree = inode
+            .i_flags
+            .contains(ext4::flags::inode::IFlags::Ext4IndexFl);
+        let block_size = self.block_size as usize;
+        let mut dir = FileHandle::new(self, inode)?;
+        let num_blocks = dir.size().div_ceil(block_size as u64) as u32;
+
+        for lblk in 0..num_blocks {
+            let pblk = match dir.physical_block(lblk) {
+                Some(pblk) => pblk,
+                None => continue,
+            };
+            let block = dir.read_logical_block(lblk)?;
+
+            // Interior nodes hide behind a fake entry spanning the whole block.
+            let is_dx_node = use_htree
+                && lblk != 0
+                && read_u32(&block, 0) == 0
+                && u16::from_le_bytes([block[4], block[5]]) as usize == block_size;
+
+            if use_htree && (lblk == 0 || is_dx_node) {
+                let (count_offset, limit, count) = if lblk == 0 {
+                    let root = ext4::structs::dir::DxRoot::from_buffer(&block, 0)?;
+                    (0x18 + root.info_length as usize, root.limit, root.count)
+                } else {
+                    let node = ext4::structs::dir::DxNode::from_buffer(&block, 0)?;
+                    (8, node.limit, node.count)
+                };
+                let tail = ext4::checksum::dx_tail_offset(block_size, count_offset, limit);
+                if let Some(tail) = tail.filter(|_| count <= limit) {
+                    found.extend(mismatch(
+                        ext4::checksum::Checksummed::DxNode {
+                            inode: inode_num,
+                            block: pblk,
+                        },
+                        read_u32(&block[tail + 4..], 0),
+                        ext4::checksum::dx_node_csum(inode_seed, &block, count_offset, count, tail),
+                    ));
+                }
+                continue;
+            }
+
+            // The leaf tail is a fake entry: inode 0, rec_len 12, no name,
+            // file type 0xDE, followed by the checksum.
+            let tail = &block[block_size - ext4::checksum::DIR_TAIL_SIZE..];
+            if read_u32(tail, 0) == 0
+                && u16::from_le_bytes([tail[4], tail[5]]) == 12
+                && tail[6] == 0
+                && tail[7] == ext4::flags::dir::FileType::Ext4FtDirCsum.bits()
+            {
+                found.extend(mismatch(
+                    ext4::checksum::Checksummed::DirBlock {
+                        inode: inode_num,
+                        block: pblk,
+                    },
+                    read_u32(tail, 2),
+                    ext4::checksum::dir_block_csum(inode_seed, &block),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks every metadata checksum on the filesystem: the superblock, group
+    /// descriptors, bitmaps, and each initialized inode along with its blocks.
+    /// Blocks of inodes that fail their own check are not followed.
+    pub fn verify_checksums(&mut self) -> Result<Vec<ext4::checksum::ChecksumMismatch>> {
+        let mut found = Vec::new();
+        found.extend(self.verify_super_block()?);
+        found.extend(self.verify_group_descs()?);
+        if !self.super_block.has_metadata_csum_feature() {
+            return Ok(found);
+        }
+
+        let inodes_per_group = self.super_block.s_inodes_per_group;
+        for group in 0..self.group_descs.len() as u32 {
+            found.extend(self.verify_bitmaps(group)?);
+
+            let desc = &self.group_descs.descs[group as usize];
+            if desc
+                .bg_flags
+                .contains(ext4::flags::group_desc::GroupFlags::INODE_UNINIT)
+            {
+                continue;
+            }
+            // Inodes past the used part of the table were never written.
+            let used = inodes_per_group.saturating_sub(desc.itable_unused());
+            for i in 0..used {
+                let inode_num = group * inodes_per_group + i + 1;
+                let raw = self.read_inode_bytes(inode_num)?;
+                if raw.iter().all(|&b| b == 0) {
+                    continue;
+                }
+                if let Some(m) = self.inode_csum_mismatch(inode_num, &raw)? {
+                    found.push(m);
+                    continue;
+                }
+                let inode = ext4::structs::Inode::from_raw(&raw)?;
+                if inode.i_links_count > 0 && inode.i_dtime == 0 {
+                    found.extend(self.verify_inode_blocks(inode_num)?);
+                }
+            }
+        }
+        Ok(found)
+    }
+}
+
+/// A mismatch report for `structure`, or `None` if the checksums agree.
+fn mismatch(
+    structure: ext4::checksum::Checksummed,
+    stored: u32,
+    computed: u32,
+) -> Option<ext4::checksum::ChecksumMismatch> {
+    (stored != computed).then_some(ext4::checksum::ChecksumMismatch {
+        structure,
+        stored,
+        computed,
+    })
+}
+
+/// Rejects superblocks that are not ext4, use features this reader cannot
+/// handle, or whose geometry would make the layout arithmetic meaningless.
+pub(crate) fn check_super_block(sb: &ext4::structs::SuperBlock) -> Result<()> {
+    if sb.s_magic != EXT4_SUPER_MAGIC {
+        return Err(Ext4Error::BadMagic(sb.s_magic));
+    }
+    // Block sizes run from 1 KiB to 64 KiB.
+    if sb.s_log_block_size > 6 {
+        return Err(Ext4Error::corrupt(
+            0,
+            format!(
+                "block size 2^{} out of range",
+                10 + sb.s_log_block_size as u64
+            ),
+        ));
+    }
+    let block_size = 1024_u64 << sb.s_log_block_size;
+    let blk = SUPERBLOCK_OFFSET / block_size;
+
+    if sb.s_blocks_per_group == 0 || sb.s_inodes_per_group == 0 {
+        return Err(Ext4Error::corrupt(blk, "empty block groups"));
+    }
+    if sb.s_first_data_block as u64 >= sb.blocks_count() {
+        return Err(Ext4Error::corrupt(blk, "no blocks past s_first_data_block"));
+    }
+    let groups =
+        (sb.blocks_count() - sb.s_first_data_block as u64).div_ceil(sb.s_blocks_per_group as u64);
+    if groups > u32::MAX as u64 {
+        return Err(Ext4Error::corrupt(blk, format!("{} block groups", groups)));
+    }
+    let desc_size = sb.desc_size() as u64;
+    if desc_size < 32 || desc_size > block_size || !desc_size.is_power_of_two() {
+        return Err(Ext4Error::corrupt(
+            blk,
+            format!("bad group descriptor size {}", desc_size),
+        ));
+    }
+    let inode_size = sb.inode_size() as u64;
+    if inode_size < 128 || inode_size > block_size || !inode_size.is_power_of_two() {
+        return Err(Ext4Error::corrupt(
+            blk,
+            format!("bad inode size {}", inode_size),
+        ));
+    }
+
+    // A group is as large as one block of bitmap can describe.
+    let bits_per_block = 8 * block_size;
+    let bigalloc = sb
+        .s_feature_ro_compat
+        .contains(ext4::flags::superblock::ROCompatibleFeatures::BIGALLOC);
+    let clusters_ok = if bigalloc {
+        sb.s_log_cluster_size >= sb.s_log_block_size
+            && sb.s_log_cluster_size - sb.s_log_block_size < 32
+            && (sb.s_clusters_per_group as u64) << (sb.s_log_cluster_size - sb.s_log_block_size)
+                == sb.s_blocks_per_group as u64
+    } else {
+        sb.s_clusters_per_group == sb.s_blocks_per_group
+    };
+    if !clusters_ok || sb.s_clusters_per_group as u64 > bits_per_block {
+        return Err(Ext4Error::corrupt(
+            blk,
+            format!(
+                "bad group geometry of {} blocks in {} clusters",
+                sb.s_blocks_per_group, sb.s_clusters_per_group
+            ),
+        ));
+    }
+    let ipg = sb.s_inodes_per_group as u64;
+    if ipg > bits_per_block || ipg < block_size / inode_size {
+        return Err(Ext4Error::corrupt(
+            blk,
+            format!("bad inodes per group {}", ipg),
+        ));
+    }
+    if sb.s_inodes_count as u64 != ipg * groups {
+        return Err(Ext4Error::corrupt(
+            blk,
+            format!(
+                "{} inodes in {} groups of {}",
+                sb.s_inodes_count, groups, ipg
+            ),
+        ));
+    }
+
+    let incompat = sb.s_feature_incompat;
+    if incompat.contains(ext4::flags::superblock::IncompatibleFeatures::COMPRESSION) {
+        return Err(Ext4Error::Unsupported("compression".to_string()));
+    }
+    if incompat.contains(ext4::flags::superblock::IncompatibleFeatures::JOURNAL_DEV) {
+        return Err(Ext4Error::Unsupported("journal_dev".to_string()));
+    }
+    Ok(())
+}
+
+/// Appends the live entries of a linear directory block, read from `blk_num`,
+/// to `entries`.
+fn parse_dir_block(
+    blk: &[u8],
+    blk_num: u64,
+    entries: &mut Vec<ext4::structs::dir::Entry2>,
+) -> Result<()> {
+    let mut offset = 0;
+    while offset + 8 <= blk.len() {
+        let bad_entry =
+            || Ext4Error::corrupt(blk_num, format!("bad directory entry at offset {}", offset));
+        // A name running past the block fails to decode.
+        let de = ext4::structs::dir::Entry2::from_buffer(blk, offset).map_err(|_| bad_entry())?;
+        let rec_len = de.rec_len as usize;
+        if rec_len < 8 + de.name_len as usize || offset + rec_len > blk.len() {
+            return Err(bad_entry());
+        }
+        offset += rec_len;
+        if de.inode == 0 {
+            continue;
+        }
+        entries.push(de);
+    }
+    Ok(())
+}
+
+/// Reads the `count` dx entries of the table starting at `table_offset`.
+/// The first entry shares its hash slot with the table's limit and count.
+fn read_dx_entries(
+    block: &[u8],
+    blk_num: u64,
+    table_offset: usize,
+    count: u16,
+) -> Result<Vec<ext4::structs::dir::DxEntry>> {
+    if table_offset + count as usize * 8 > block.len() {
+        return Err(Ext4Error::corrupt(blk_num, "dx table overflows its block"));
+    }
+    (0..count as usize)
+        .map(|i| ext4::structs::dir::DxEntry::from_buffer(block, table_offset + i * 8))
+        .collect()
+}
+
+/// Collects the entries below logical block `lblk`, which is a leaf block
+/// when `levels` is 0 and a dx node otherwise.
+fn walk_dx_node<D: BlockDevice>(
+    dir: &mut FileHandle<D>,
+    lblk: u32,
+    levels: u8,
+    entries: &mut Vec<ext4::structs::dir::Entry2>,
+) -> Result<()> {
+    let blk = dir.read_logical_block(lblk)?;
+    let blk_num = dir.physical_block(lblk).unwrap_or(0);
+    if levels == 0 {
+        return parse_dir_block(&blk, blk_num, entries);
+    }
+
+    let dx_node = ext4::structs::dir::DxNode::from_buffer(&blk, 0)?;
+    for dentry in read_dx_entries(&blk, blk_num, 8, dx_node.count)? {
+        walk_dx_node(dir, dentry.block, levels - 1, entries)?;
+    }
+    Ok(())
+}
+
+/// Index of the dx entry whose range covers `hash`. Entry 0 has no hash
+/// of its own and covers everything below entry 1.
+fn dx_find(table: &[ext4::structs::dir::DxEntry], hash: u32) -> usize {
+    table[1..].partition_point(|e| e.hash <= hash)
+}
+
+/// Descends from the dx root to the leaf block `hash` falls in and searches it for `name`.
+/// A root claiming `max_levels` or more levels of dx nodes is corrupt.
+fn dx_lookup<D: BlockDevice>(
+    dir: &mut FileHandle<D>,
+    root: &[u8],
+    dx_root: &ext4::structs::dir::DxRoot,
+    max_levels: u8,
+    hash: u32,
+    name: &[u8],
+) -> Result<Option<ext4::structs::dir::Entry2>> {
+    let root_blk = dir.physical_block(0).unwrap_or(0);
+    if dx_root.indirect_levels >= max_levels {
+        return Err(Ext4Error::corrupt(
+            root_blk,
+            format!("hash tree too deep: {} levels", dx_root.indirect_levels),
+        ));
+    }
+    let table_offset = 0x18 + dx_root.info_length as usize;
+    let mut table = read_dx_entries(root, root_blk, table_offset, dx_root.count)?;
+
+    // The index table and entry taken at each level, root first.
+    let mut path = Vec::new();
+    loop {
+        if table.is_empty() {
+            return Ok(None);
+        }
+        let idx = dx_find(&table, hash);
+        let lblk = table[idx].block;
+        path.push((table, idx));
+        if path.len() > dx_root.indirect_levels as usize {
+            break;
+        }
+        table = read_dx_node(dir, lblk)?;
+    }
+
+    loop {
+        let (table, idx) = &path[path.len() - 1];
+        let lblk = table[*idx].block;
+        let blk = dir.read_logical_block(lblk)?;
+        let mut entries = Vec::new();
+        parse_dir_block(&blk, dir.physical_block(lblk).unwrap_or(0), &mut entries)?;
+        if let Some(entry) = entries.into_iter().find(|e| e.name() == name) {
+            return Ok(Some(entry));
+        }
+        if !dx_next_block(dir, &mut path, hash)? {
+            return Ok(None);
+        }
+    }
+}
+
+/// Moves `path` on to the next leaf block if it continues a run of
+/// entries colliding on `hash`, which its index entry marks by a set low
+/// bit. As in the kernel's ext4_htree_next_block, the next entry is taken
+/// at the lowest level that has one left, and its subtree is entered along
+/// the first entries below it.
+fn dx_next_block<D: BlockDevice>(
+    dir: &mut FileHandle<D>,
+    path: &mut Vec<(Vec<ext4::structs::dir::DxEntry>, usize)>,
+    hash: u32,
+) -> Result<bool> {
+    let depth = path.len();
+    while let Some((table, idx)) = path.last_mut() {
+        *idx += 1;
+        if *idx < table.len() {
+            break;
+        }
+        path.pop();
+    }
+    let Some((table, idx)) = path.last() else {
+        return Ok(false);
+    };
+    let next = table[*idx].hash;
+    if next & 1 == 0 || next & !1 != hash {
+        return Ok(false);
+    }
+    while path.len() < depth {
+        let (table, idx) = &path[path.len() - 1];
+        let child = read_dx_node(dir, table[*idx].block)?;
+        if child.is_empty() {
+            return Ok(false);
+        }
+        path.push((child, 0));
+    }
+    Ok(true)
+}
+
+/// The index entries of the dx node at logical block `lblk`.
+fn read_dx_node<D: BlockDevice>(
+    dir: &mut FileHandle<D>,
+    lblk: u32,
+) -> Result<Vec<ext4::structs::dir::DxEntry>> {
+    let blk = dir.read_logical_block(lblk)?;
+    let dx_node = ext4::structs::dir::DxNode::from_buffer(&blk, 0)?;
+    let blk_num = dir.physical_block(lblk).unwrap_or(0);
+    read_dx_entries(&blk, blk_num, 8, dx_node.count)
+}
+
+/// Splits a path into its components, in reverse so they can be popped in order.
+fn split_path(path: &[u8]) -> Vec<Vec<u8>> {
+    path.split(|&c| c == b'/')
+        .filter(|c| !c.is_empty())
+        .rev()
+        .map(|c| c.to_vec())
+        .collect()
+}
+
+/// Reads the `idx`th little-endian u32 of `buf`.
+fn read_u32(buf: &[u8], idx: usize) -> u32 {
+    u32::from_le_bytes(buf[idx * 4..idx * 4 + 4].try_into().unwrap())
+}
+
+/// Records that logical block `lblk` lives at `pblk`, growing the last
+/// extent when the block continues it.
+fn push_mapped_block(extents: &mut Vec<ext4::structs::extent::Extent>, lblk: u64, pblk: u32) {
+    // Block pointers of 0 are holes, and ext4 logical block numbers are 32-bit.
+    if pblk == 0 || lblk > u32::MAX as u64 {
+        return;
+    }
+    let lblk = lblk as u32;
+
+    if let Some(last) = extents.last_mut() {
+        let len = last.len();
+        if len < 32768
+            && last.ee_block as u64 + len as u64 == lblk as u64
+            && last.start() + len as u64 == pblk as u64
+        {
+            last.ee_len += 1;
+            return;
         }
-        extents
     }
+    extents.push(ext4::structs::extent::Extent {
+        ee_block: lblk,
+        ee_len: 1,
+        ee_start_hi: 0,
+        ee_start_lo: pblk,
+    });
 }
@@ -0,0 +1,100 @@
+use std::fmt;
+use std::io;
+
+/// Errors raised while reading an ext4 image.
+#[derive(Debug)]
+pub enum Ext4Error {
+    /// Reading the image failed.
+    Io(io::Error),
+    /// The superblock magic is not 0xEF53; holds the value found instead.
+    BadMagic(u16),
+    /// The filesystem uses a feature this reader cannot handle.
+    Unsupported(String),
+    /// On-disk metadata is inconsistent. `block` is the block holding it,
+    /// or 0 when the damaged structure lives inside an inode.
+    Corrupt { block: u64, reason: String },
+    /// A structure starting at `offset` of a buffer runs past its end.
+    Truncated {
+        structure: &'static str,
+        offset: usize,
+    },
+    /// A path component or inode does not exist.
+    NotFound(String),
+    /// A path component that is not a directory was used as one.
+    NotADirectory(String),
+    /// Too many symlinks were followed while resolving a path.
+    TooManyLinks(String),
+    /// A symlink's target is encrypted and cannot be followed.
+    Encrypted(String),
+    /// The MBR or GPT partition table of a disk image is malformed.
+    BadPartitionTable(String),
+}
+
+pub type Result<T> = std::result::Result<T, Ext4Error>;
+
+impl Ext4Error {
+    pub fn corrupt(block: u64, reason: impl Into<String>) -> Self {
+        Ext4Error::Corrupt {
+            block,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for Ext4Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ext4Error::Io(e) => write!(f, "I/O error: {}", e),
+            Ext4Error::BadMagic(magic) => {
+                write!(
+                    f,
+                    "Bad superblock magic {:#06x}, not an ext4 filesystem",
+                    magic
+                )
+            }
+            Ext4Error::Unsupported(feature) => write!(f, "Unsupported feature: {}", feature),
+            Ext4Error::Corrupt { block, reason } => {
+                write!(f, "Corrupt filesystem at block {}: {}", block, reason)
+            }
+            Ext4Error::Truncated { structure, offset } => {
+                write!(f, "Truncated {} at offset {}", structure, offset)
+            }
+            Ext4Error::NotFound(p) => write!(f, "{}: No such file or directory", p),
+            Ext4Error::NotADirectory(p) => write!(f, "{}: Not a directory", p),
+            Ext4Error::TooManyLinks(p) => write!(f, "{}: Too many levels of symbolic links", p),
+            Ext4Error::Encrypted(p) => write!(f, "{}: Symlink target is encrypted", p),
+            Ext4Error::BadPartitionTable(reason) => write!(f, "Bad partition table: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Ext4Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Ext4Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Ext4Error {
+    fn from(e: io::Error) -> Self {
+        // Errors raised by FileHandle's Read impl come back out as they went in.
+        if e.get_ref().is_some_and(|inner| inner.is::<Ext4Error>()) {
+            if let Some(Ok(inner)) = e.into_inner().map(|inner| inner.downcast::<Ext4Error>()) {
+                return *inner;
+            }
+            unreachable!("checked above");
+        }
+        Ext4Error::Io(e)
+    }
+}
+
+impl From<Ext4Error> for io::Error {
+    fn from(e: Ext4Error) -> Self {
+        match e {
+            Ext4Error::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
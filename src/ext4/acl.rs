@@ -0,0 +1,249 @@
+use super::inode::FileMode;
+use super::{Decoder, LoadAble};
+use crate::error::Result;
+use bitflags::bitflags;
+
+/// Version stored in the header of an on-disk ACL.
+pub const ACL_VERSION: u32 = 0x0001;
+
+#[allow(dead_code)]
+#[derive(Debug)]
+#[repr(C)]
+pub struct AclHeader {
+    /// ACL format version, always 1.
+    pub a_version: u32,
+}
+impl LoadAble for AclHeader {
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        Ok(AclHeader {
+            a_version: d.read()?,
+        })
+    }
+}
+
+/// Entry for tags that do not name a user or group.
+#[allow(dead_code)]
+#[derive(Debug)]
+#[repr(C)]
+pub struct AclEntryShort {
+    /// Entry tag.
+    pub e_tag: u16,
+
+    /// Permission bits.
+    pub e_perm: u16,
+}
+impl LoadAble for AclEntryShort {
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        Ok(AclEntryShort {
+            e_tag: d.read()?,
+            e_perm: d.read()?,
+        })
+    }
+}
+
+/// Entry for ACL_USER and ACL_GROUP tags.
+#[allow(dead_code)]
+#[derive(Debug)]
+#[repr(C)]
+pub struct AclEntryLong {
+    /// Entry tag.
+    pub e_tag: u16,
+
+    /// Permission bits.
+    pub e_perm: u16,
+
+    /// User or group id.
+    pub e_id: u32,
+}
+impl LoadAble for AclEntryLong {
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        Ok(AclEntryLong {
+            e_tag: d.read()?,
+            e_perm: d.read()?,
+            e_id: d.read()?,
+        })
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct AclPerm: u16 {
+        const EXECUTE = 0x1;
+        const WRITE = 0x2;
+        const READ = 0x4;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AclTag {
+    /// Permissions of the file owner.
+    UserObj,
+    /// Permissions of a named user.
+    User(u32),
+    /// Permissions of the owning group.
+    GroupObj,
+    /// Permissions of a named group.
+    Group(u32),
+    /// Upper bound for named users, groups and the owning group.
+    Mask,
+    /// Permissions of everyone else.
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AclEntry {
+    pub tag: AclTag,
+    pub perm: AclPerm,
+}
+
+/// A decoded POSIX access or default ACL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Acl {
+    pub entries: Vec<AclEntry>,
+}
+
+impl Acl {
+    /// Decodes the value of a system.posix_acl_access or
+    /// system.posix_acl_default extended attribute.
+    pub fn from_xattr(buf: &[u8]) -> Option<Self> {
+        let header_size = std::mem::size_of::<AclHeader>();
+        if buf.len() < header_size || AclHeader::from_buffer(buf, 0).ok()?.a_version != ACL_VERSION
+        {
+            return None;
+        }
+
+        let mut entries = Vec::new();
+        let mut offset = header_size;
+        while offset < buf.len() {
+            if buf.len() - offset < std::mem::size_of::<AclEntryShort>() {
+                return None;
+            }
+            let short = AclEntryShort::from_buffer(buf, offset).ok()?;
+            let perm = AclPerm::from_bits_retain(short.e_perm);
+            let tag = match short.e_tag {
+                0x01 => AclTag::UserObj,
+                0x04 => AclTag::GroupObj,
+                0x10 => AclTag::Mask,
+                0x20 => AclTag::Other,
+                0x02 | 0x08 => {
+                    if buf.len() - offset < std::mem::size_of::<AclEntryLong>() {
+                        return None;
+                    }
+                    let long = AclEntryLong::from_buffer(buf, offset).ok()?;
+                    offset += std::mem::size_of::<AclEntryLong>();
+                    let tag = if long.e_tag == 0x02 {
+                        AclTag::User(long.e_id)
+                    } else {
+                        AclTag::Group(long.e_id)
+                    };
+                    entries.push(AclEntry { tag, perm });
+                    continue;
+                }
+                _ => return None,
+            };
+            offset += std::mem::size_of::<AclEntryShort>();
+            entries.push(AclEntry { tag, perm });
+        }
+        Some(Acl { entries })
+    }
+
+    /// The minimal ACL equivalent to the permission bits of `mode`.
+    pub fn from_mode(mode: &FileMode) -> Self {
+        let bits = mode.bits();
+        let perm = |shift: u16| AclPerm::from_bits_truncate((bits >> shift) & 0x7);
+        Acl {
+            entries: vec![
+                AclEntry {
+                    tag: AclTag::UserObj,
+                    perm: perm(6),
+                },
+                AclEntry {
+                    tag: AclTag::GroupObj,
+                    perm: perm(3),
+                },
+                AclEntry {
+                    tag: AclTag::Other,
+                    perm: perm(0),
+                },
+            ],
+        }
+    }
+
+    fn perm_of(&self, tag: AclTag) -> Option<AclPerm> {
+        self.entries.iter().find(|e| e.tag == tag).map(|e| e.perm)
+    }
+
+    /// Permissions granted to a process running as `uid` with groups `gids`
+    /// on a file owned by `owner_uid`:`owner_gid`, following the POSIX
+    /// access check order: owner, named users, groups, then other.
+    pub fn effective_perms(
+        &self,
+        owner_uid: u32,
+        owner_gid: u32,
+        uid: u32,
+        gids: &[u32],
+    ) -> AclPerm {
+        let mask = self.perm_of(AclTag::Mask).unwrap_or(AclPerm::all());
+
+        if uid == owner_uid {
+            return self.perm_of(AclTag::UserObj).unwrap_or(AclPerm::empty());
+        }
+        if let Some(perm) = self.perm_of(AclTag::User(uid)) {
+            return perm & mask;
+        }
+
+        let mut matched = false;
+        let mut group_perm = AclPerm::empty();
+        for entry in &self.entries {
+            let applies = match entry.tag {
+                AclTag::GroupObj => gids.contains(&owner_gid),
+                AclTag::Group(gid) => gids.contains(&gid),
+                _ => false,
+            };
+            if applies {
+                matched = true;
+                group_perm |= entry.perm;
+            }
+        }
+        if matched {
+            return group_perm & mask;
+        }
+
+        self.perm_of(AclTag::Other).unwrap_or(AclPerm::empty())
+    }
+}
+
+fn perm_str(perm: AclPerm) -> String {
+    [
+        (AclPerm::READ, 'r'),
+        (AclPerm::WRITE, 'w'),
+        (AclPerm::EXECUTE, 'x'),
+    ]
+    .iter()
+    .map(|(p, c)| if perm.contains(*p) { *c } else { '-' })
+    .collect()
+}
+
+/// Renders the ACL the way getfacl does, one entry per line, noting
+/// the effective permissions where the mask takes some away.
+impl std::fmt::Display for Acl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mask = self.perm_of(AclTag::Mask);
+        for entry in &self.entries {
+            let (name, masked) = match entry.tag {
+                AclTag::UserObj => ("user::".to_string(), false),
+                AclTag::User(id) => (format!("user:{}:", id), true),
+                AclTag::GroupObj => ("group::".to_string(), true),
+                AclTag::Group(id) => (format!("group:{}:", id), true),
+                AclTag::Mask => ("mask::".to_string(), false),
+                AclTag::Other => ("other::".to_string(), false),
+            };
+            write!(f, "{}{}", name, perm_str(entry.perm))?;
+            if let Some(mask) = mask.filter(|m| masked && !m.contains(entry.perm)) {
+                write!(f, "\t#effective:{}", perm_str(entry.perm & mask))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
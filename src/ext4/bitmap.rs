@@ -0,0 +1,68 @@
+use std::ops::Range;
+
+/// Allocation bitmap of one block group. Bit `i` set means block (cluster)
+/// or inode `i` of the group is in use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bitmap {
+    bits: Vec<u8>,
+    len: u32,
+}
+
+impl Bitmap {
+    /// Wraps the first `len` bits of `bits`; padding past them is dropped.
+    pub fn new(mut bits: Vec<u8>, len: u32) -> Self {
+        bits.resize((len as usize).div_ceil(8), 0);
+        if let Some(last) = bits.last_mut().filter(|_| !len.is_multiple_of(8)) {
+            *last &= (1 << (len % 8)) - 1;
+        }
+        Bitmap { bits, len }
+    }
+
+    /// A bitmap of `len` clear bits.
+    pub fn empty(len: u32) -> Self {
+        Bitmap::new(Vec::new(), len)
+    }
+
+    /// Number of bits covered.
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_set(&self, bit: u32) -> bool {
+        bit < self.len && self.bits[bit as usize / 8] & (1 << (bit % 8)) != 0
+    }
+
+    /// Sets `bit`; bits past the end are ignored.
+    pub fn set(&mut self, bit: u32) {
+        if bit < self.len {
+            self.bits[bit as usize / 8] |= 1 << (bit % 8);
+        }
+    }
+
+    /// Number of set bits.
+    pub fn count_set(&self) -> u32 {
+        self.bits.iter().map(|b| b.count_ones()).sum()
+    }
+
+    /// Maximal runs of bits equal to `set`, in ascending order.
+    pub fn ranges(&self, set: bool) -> impl Iterator<Item = Range<u32>> + '_ {
+        let mut bit = 0;
+        std::iter::from_fn(move || {
+            while bit < self.len && self.is_set(bit) != set {
+                bit += 1;
+            }
+            if bit == self.len {
+                return None;
+            }
+            let start = bit;
+            while bit < self.len && self.is_set(bit) == set {
+                bit += 1;
+            }
+            Some(start..bit)
+        })
+    }
+}
@@ -0,0 +1,383 @@
+use std::mem::offset_of;
+
+use super::group_desc::GroupDesc;
+use super::inode::Inode;
+use super::superblock::{IncompatibleFeatures, SuperBlock};
+use super::xattr::XattrHeader;
+
+/// CRC32c (Castagnoli) lookup table, reflected polynomial.
+const CRC32C_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0x82F63B78
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// CRC16 (ANSI) lookup table, reflected polynomial, as used by gdt_csum.
+const CRC16_TABLE: [u16; 256] = {
+    let mut table = [0u16; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u16;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xA001
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Size of the checksum tail at the end of a directory leaf block.
+pub const DIR_TAIL_SIZE: usize = 12;
+
+/// Size of the checksum tail following the entries of a dx root or node.
+const DX_TAIL_SIZE: usize = 8;
+
+/// Continues a CRC32c over `data`. Like the kernel's ext4_chksum, there is no
+/// final inversion, so checksums can be chained across discontiguous pieces.
+pub fn crc32c(mut crc: u32, data: &[u8]) -> u32 {
+    for &b in data {
+        crc = CRC32C_TABLE[((crc ^ b as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+/// Continues a CRC16 over `data`.
+pub fn crc16(mut crc: u16, data: &[u8]) -> u16 {
+    for &b in data {
+        crc = CRC16_TABLE[((crc ^ b as u16) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+/// Seed all metadata checksums start from: s_checksum_seed with the
+/// csum_seed feature, so the UUID can change, or else derived from the UUID.
+pub fn fs_seed(sb: &SuperBlock) -> u32 {
+    if sb
+        .s_feature_incompat
+        .contains(IncompatibleFeatures::CSUM_SEED)
+    {
+        sb.s_checksum_seed
+    } else {
+        crc32c(!0, &sb.s_uuid)
+    }
+}
+
+/// Checksum of a raw superblock, covering everything before s_checksum.
+pub fn super_block_csum(raw: &[u8]) -> u32 {
+    crc32c(!0, &raw[..offset_of!(SuperBlock, s_checksum)])
+}
+
+/// Checksum of the raw descriptor of group `group`, with bg_checksum taken as zero.
+pub fn group_desc_csum(seed: u32, group: u32, raw: &[u8]) -> u16 {
+    let offset = offset_of!(GroupDesc, bg_checksum);
+    let mut crc = crc32c(seed, &group.to_le_bytes());
+    crc = crc32c(crc, &raw[..offset]);
+    crc = crc32c(crc, &[0; 2]);
+    crc = crc32c(crc, &raw[offset + 2..]);
+    crc as u16
+}
+
+/// Legacy gdt_csum checksum of the raw descriptor of group `group`: crc16
+/// over the UUID, the group number and the descriptor minus bg_checksum.
+/// Only 64-bit descriptors have bytes past bg_checksum to cover.
+pub fn group_desc_crc16(uuid: &[u8; 16], group: u32, raw: &[u8]) -> u16 {
+    let offset = offset_of!(GroupDesc, bg_checksum);
+    let mut crc = crc16(!0, uuid);
+    crc = crc16(crc, &group.to_le_bytes());
+    crc = crc16(crc, &raw[..offset]);
+    crc16(crc, &raw[offset + 2..])
+}
+
+/// Per-inode seed that the checksums of an inode and its blocks start from.
+pub fn inode_seed(seed: u32, inode_num: u32, generation: u32) -> u32 {
+    let crc = crc32c(seed, &inode_num.to_le_bytes());
+    crc32c(crc, &generation.to_le_bytes())
+}
+
+/// Checksum of a raw inode record, with both checksum halves taken as zero.
+/// Inodes without room for i_checksum_hi only keep the low 16 bits.
+pub fn inode_csum(seed: u32, inode_num: u32, raw: &[u8]) -> u32 {
+    let mut record = raw.to_vec();
+    let generation = offset_of!(Inode, i_generation);
+    let generation = u32::from_le_bytes([
+        record[generation],
+        record[generation + 1],
+        record[generation + 2],
+        record[generation + 3],
+    ]);
+
+    let lo = offset_of!(Inode, l_i_checksum_lo);
+    record[lo..lo + 2].fill(0);
+    let hi = offset_of!(Inode, i_checksum_hi);
+    if inode_has_csum_hi(&record) {
+        record[hi..hi + 2].fill(0);
+    }
+
+    let crc = crc32c(inode_seed(seed, inode_num, generation), &record);
+    if inode_has_csum_hi(&record) {
+        crc
+    } else {
+        crc & 0xFFFF
+    }
+}
+
+/// Whether i_extra_isize covers i_checksum_hi.
+pub fn inode_has_csum_hi(raw: &[u8]) -> bool {
+    let extra = offset_of!(Inode, i_extra_isize);
+    if raw.len() < extra + 2 {
+        return false;
+    }
+    let extra_isize = u16::from_le_bytes([raw[extra], raw[extra + 1]]) as usize;
+    128 + extra_isize >= offset_of!(Inode, i_checksum_hi) + 2
+}
+
+/// Checksum of an extent tree block, covering everything before the tail
+/// at `tail_offset`.
+pub fn extent_block_csum(inode_seed: u32, block: &[u8], tail_offset: usize) -> u32 {
+    crc32c(inode_seed, &block[..tail_offset])
+}
+
+/// Checksum of a directory leaf block, covering everything before its tail.
+pub fn dir_block_csum(inode_seed: u32, block: &[u8]) -> u32 {
+    crc32c(inode_seed, &block[..block.len() - DIR_TAIL_SIZE])
+}
+
+/// Checksum of a dx root or node whose limit/count header sits at
+/// `count_offset`, covering the used entries and the tail at `tail_offset`.
+pub fn dx_node_csum(
+    inode_seed: u32,
+    block: &[u8],
+    count_offset: usize,
+    count: u16,
+    tail_offset: usize,
+) -> u32 {
+    let crc = crc32c(inode_seed, &block[..count_offset + count as usize * 8]);
+    // dt_reserved, then the checksum itself as zero.
+    let crc = crc32c(crc, &block[tail_offset..tail_offset + 4]);
+    crc32c(crc, &[0; 4])
+}
+
+/// Where the dx tail of a root or node with `limit` entries starts, if the
+/// block has room for one.
+pub fn dx_tail_offset(block_len: usize, count_offset: usize, limit: u16) -> Option<usize> {
+    let offset = count_offset + limit as usize * 8;
+    (offset + DX_TAIL_SIZE <= block_len).then_some(offset)
+}
+
+/// Checksum of the first `len` bytes of a block or inode bitmap.
+pub fn bitmap_csum(seed: u32, bitmap: &[u8], len: usize) -> u32 {
+    crc32c(seed, &bitmap[..len.min(bitmap.len())])
+}
+
+/// Checksum of the external xattr block at `block_num`, with h_checksum taken as zero.
+pub fn xattr_block_csum(seed: u32, block_num: u64, block: &[u8]) -> u32 {
+    let offset = offset_of!(XattrHeader, h_checksum);
+    let mut crc = crc32c(seed, &block_num.to_le_bytes());
+    crc = crc32c(crc, &block[..offset]);
+    crc = crc32c(crc, &[0; 4]);
+    crc32c(crc, &block[offset + 4..])
+}
+
+/// A piece of metadata protected by a checksum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checksummed {
+    SuperBlock,
+    GroupDesc(u32),
+    BlockBitmap(u32),
+    InodeBitmap(u32),
+    Inode(u32),
+    ExtentBlock { inode: u32, block: u64 },
+    DirBlock { inode: u32, block: u64 },
+    DxNode { inode: u32, block: u64 },
+    XattrBlock { block: u64 },
+}
+
+impl std::fmt::Display for Checksummed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Checksummed::SuperBlock => write!(f, "superblock"),
+            Checksummed::GroupDesc(g) => write!(f, "group descriptor {}", g),
+            Checksummed::BlockBitmap(g) => write!(f, "block bitmap of group {}", g),
+            Checksummed::InodeBitmap(g) => write!(f, "inode bitmap of group {}", g),
+            Checksummed::Inode(i) => write!(f, "inode {}", i),
+            Checksummed::ExtentBlock { inode, block } => {
+                write!(f, "extent block {} of inode {}", block, inode)
+            }
+            Checksummed::DirBlock { inode, block } => {
+                write!(f, "directory block {} of inode {}", block, inode)
+            }
+            Checksummed::DxNode { inode, block } => {
+                write!(f, "htree node {} of inode {}", block, inode)
+            }
+            Checksummed::XattrBlock { block } => write!(f, "xattr block {}", block),
+        }
+    }
+}
+
+/// A structure whose stored checksum does not match its contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecksumMismatch {
+    pub structure: Checksummed,
+    pub stored: u32,
+    pub computed: u32,
+}
+
+impl std::fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: checksum {:#010x}, expected {:#010x}",
+            self.structure, self.stored, self.computed
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// UUID of the filesystem the records below were taken from, made with
+    /// mke2fs -b 1024 -O metadata_csum and checked clean by e2fsck.
+    const UUID: [u8; 16] = [
+        0x6c, 0x0e, 0x2b, 0xa1, 0x3f, 0x4d, 0x4a, 0x5e, 0x9b, 0x61, 0x2d, 0x8f, 0x7c, 0x9e, 0x0a,
+        0x13,
+    ];
+
+    /// Inode 313, a regular file with generation 0x9e3779b9, from a filesystem
+    /// with 256-byte inodes. Everything past these bytes is zero.
+    const INODE_256: [u8; 160] = [
+        0xa4, 0x81, 0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x16, 0xa7, 0xd4, 0x6a, 0x16, 0xa7, 0xd4,
+        0x6a, 0x16, 0xa7, 0xd4, 0x6a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x02, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0a, 0xf3, 0x01, 0x00, 0x04,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+        0x2c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xb9, 0x79, 0x37, 0x9e, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x20, 0xad, 0x00, 0x00, 0x20, 0x00, 0x43, 0xde, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xf1, 0x53, 0x65, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    /// The same file on a filesystem with 128-byte inodes, which only has
+    /// room for the low half of the checksum.
+    const INODE_128: [u8; 128] = [
+        0xa4, 0x81, 0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x1c, 0xa7, 0xd4, 0x6a, 0x16, 0xa7, 0xd4,
+        0x6a, 0x16, 0xa7, 0xd4, 0x6a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x02, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0a, 0xf3, 0x01, 0x00, 0x04,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+        0x2c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xb9, 0x79, 0x37, 0x9e, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0xcc, 0xc4, 0x00, 0x00,
+    ];
+
+    /// Used part of the 1024-byte dx root of inode 12 (generation
+    /// 0x2545f491): the dot entries, the root info and 13 index entries.
+    const DX_ROOT: [u8; 136] = [
+        0x0c, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x01, 0x02, 0x2e, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00,
+        0x00, 0xf4, 0x03, 0x02, 0x02, 0x2e, 0x2e, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x08,
+        0x00, 0x00, 0x7b, 0x00, 0x0d, 0x00, 0x01, 0x00, 0x00, 0x00, 0xaa, 0x60, 0x55, 0x11, 0x02,
+        0x00, 0x00, 0x00, 0x50, 0x06, 0xd8, 0x28, 0x03, 0x00, 0x00, 0x00, 0x1a, 0x45, 0x12, 0x44,
+        0x04, 0x00, 0x00, 0x00, 0x38, 0x67, 0x98, 0x55, 0x05, 0x00, 0x00, 0x00, 0x24, 0x22, 0xa4,
+        0x66, 0x06, 0x00, 0x00, 0x00, 0xa0, 0x73, 0xe5, 0x7a, 0x07, 0x00, 0x00, 0x00, 0x30, 0x8d,
+        0x00, 0x90, 0x08, 0x00, 0x00, 0x00, 0xf4, 0xae, 0x91, 0xa4, 0x09, 0x00, 0x00, 0x00, 0x1e,
+        0xdf, 0x93, 0xc1, 0x0a, 0x00, 0x00, 0x00, 0xfa, 0xf0, 0x8a, 0xd5, 0x0b, 0x00, 0x00, 0x00,
+        0xbe, 0x2b, 0xf0, 0xea, 0x0c, 0x00, 0x00, 0x00, 0xee, 0x6c, 0xbf, 0xf9, 0x0d, 0x00, 0x00,
+        0x00,
+    ];
+
+    /// The dx tail at the end of the same block.
+    const DX_TAIL: [u8; 8] = [0x00, 0x00, 0x00, 0x00, 0x20, 0x24, 0xb1, 0x4b];
+
+    /// Group 0 descriptor of a 32-bit filesystem with the same UUID made with
+    /// uninit_bg instead of metadata_csum, bg_checksum included.
+    const GROUP_DESC_32: [u8; 32] = [
+        0x0a, 0x00, 0x00, 0x00, 0x1a, 0x00, 0x00, 0x00, 0x2a, 0x00, 0x00, 0x00, 0xa6, 0x07, 0xf5,
+        0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xf5, 0x00,
+        0x72, 0xe7,
+    ];
+
+    fn seed() -> u32 {
+        crc32c(!0, &UUID)
+    }
+
+    #[test]
+    fn crc32c_check_value() {
+        assert_eq!(!crc32c(!0, b"123456789"), 0xE3069283);
+        assert_eq!(
+            crc32c(crc32c(!0, b"1234"), b"56789"),
+            crc32c(!0, b"123456789")
+        );
+    }
+
+    #[test]
+    fn crc16_check_value() {
+        assert_eq!(crc16(!0, b"123456789"), 0x4B37);
+    }
+
+    #[test]
+    fn group_desc_crc16_matches_e2fsprogs() {
+        assert_eq!(group_desc_crc16(&UUID, 0, &GROUP_DESC_32), 0xe772);
+        assert_ne!(group_desc_crc16(&UUID, 1, &GROUP_DESC_32), 0xe772);
+    }
+
+    #[test]
+    fn inode_csum_matches_e2fsprogs() {
+        let mut raw = [0u8; 256];
+        raw[..INODE_256.len()].copy_from_slice(&INODE_256);
+        assert!(inode_has_csum_hi(&raw));
+        assert_eq!(inode_csum(seed(), 313, &raw), 0xde43ad20);
+    }
+
+    #[test]
+    fn small_inode_csum_is_truncated() {
+        assert!(!inode_has_csum_hi(&INODE_128));
+        assert_eq!(inode_csum(seed(), 313, &INODE_128), 0xc4cc);
+    }
+
+    #[test]
+    fn csum_hi_needs_extra_isize_to_cover_it() {
+        let extra = offset_of!(Inode, i_extra_isize);
+        let mut raw = [0u8; 256];
+        raw[..INODE_256.len()].copy_from_slice(&INODE_256);
+        raw[extra] = 2;
+        assert!(!inode_has_csum_hi(&raw));
+        assert_eq!(inode_csum(seed(), 313, &raw) >> 16, 0);
+        raw[extra] = 4;
+        assert!(inode_has_csum_hi(&raw));
+    }
+
+    #[test]
+    fn dx_node_csum_matches_e2fsprogs() {
+        let mut block = [0u8; 1024];
+        block[..DX_ROOT.len()].copy_from_slice(&DX_ROOT);
+        let tail = dx_tail_offset(block.len(), 0x20, 123).unwrap();
+        assert_eq!(tail, 1016);
+        block[tail..].copy_from_slice(&DX_TAIL);
+        let seed = inode_seed(seed(), 12, 0x2545f491);
+        assert_eq!(dx_node_csum(seed, &block, 0x20, 13, tail), 0x4bb12420);
+    }
+}
@@ -1,4 +1,5 @@
-use super::LoadAble;
+use super::{Decoder, LoadAble};
+use crate::error::Result;
 use bitflags::bitflags;
 
 #[allow(dead_code)]
@@ -17,10 +18,25 @@ pub struct DirEntry {
     /// File name.
     pub name: [u8; 255],
 }
-impl LoadAble for DirEntry {}
+impl LoadAble for DirEntry {
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        let inode = d.read()?;
+        let rec_len = d.read()?;
+        let name_len: u16 = d.read()?;
+        let mut name = [0u8; 255];
+        let len = (name_len as usize).min(name.len());
+        name[..len].copy_from_slice(d.bytes(len)?);
+        Ok(DirEntry {
+            inode,
+            rec_len,
+            name_len,
+            name,
+        })
+    }
+}
 
 bitflags! {
-    #[derive(Debug)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct FileType:u8 {
         const Ext4FtUnknown = 0;
         const Ext4FtRegFile = 1;
@@ -54,12 +70,46 @@ pub struct DirEntry2 {
     /// File name.
     pub name: [u8; 255],
 }
-impl LoadAble for DirEntry2 {}
+/// Only the name_len bytes of the name are read; the rest of the array is zero.
+impl LoadAble for DirEntry2 {
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        let inode = d.read()?;
+        let rec_len = d.read()?;
+        let name_len: u8 = d.read()?;
+        let file_type = d.flags()?;
+        let mut name = [0u8; 255];
+        name[..name_len as usize].copy_from_slice(d.bytes(name_len as usize)?);
+        Ok(DirEntry2 {
+            inode,
+            rec_len,
+            name_len,
+            file_type,
+            name,
+        })
+    }
+}
 impl DirEntry2 {
+    /// Builds an entry that is not backed by an on-disk record,
+    /// such as the "." and ".." of an inline directory.
+    pub fn new(inode: u32, name: &[u8], file_type: FileType) -> Self {
+        let mut entry_name = [0u8; 255];
+        entry_name[..name.len()].copy_from_slice(name);
+        DirEntry2 {
+            inode,
+            rec_len: (8 + name.len() as u16).next_multiple_of(4),
+            name_len: name.len() as u8,
+            file_type,
+            name: entry_name,
+        }
+    }
+
+    /// Raw name bytes of this entry.
+    pub fn name(&self) -> &[u8] {
+        &self.name[0..self.name_len as usize]
+    }
+
     pub fn to_char(&self) -> String {
-        std::str::from_utf8(&self.name[0..self.name_len as usize])
-            .unwrap()
-            .to_string()
+        String::from_utf8_lossy(self.name()).into_owned()
     }
 }
 
@@ -73,7 +123,14 @@ pub struct DirEntryHash {
     /// The minor hash of the directory name
     pub minor_hash: u32,
 }
-impl LoadAble for DirEntryHash {}
+impl LoadAble for DirEntryHash {
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        Ok(DirEntryHash {
+            hash: d.read()?,
+            minor_hash: d.read()?,
+        })
+    }
+}
 
 #[allow(dead_code)]
 #[derive(Debug)]
@@ -114,5 +171,67 @@ pub struct DxRoot {
     pub block: u32,
     // pub entries: [DxEntry],
 }
-impl LoadAble for DxEntry {}
-impl LoadAble for DxRoot {}
+
+/// Interior node of a multi-level hash tree. The fake directory entry
+/// spans the whole block so linear readers skip it.
+#[allow(dead_code)]
+#[derive(Debug)]
+#[repr(C)]
+pub struct DxNode {
+    // Fake directory entry
+    pub fake_inode: u32,
+    pub fake_rec_len: u16,
+    pub name_len: u8,
+    pub file_type: FileType,
+
+    // limits
+    pub limit: u16,
+    pub count: u16,
+    pub block: u32,
+    // pub entries: [DxEntry],
+}
+impl LoadAble for DxEntry {
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        Ok(DxEntry {
+            hash: d.read()?,
+            block: d.read()?,
+        })
+    }
+}
+impl LoadAble for DxRoot {
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        Ok(DxRoot {
+            dotinode: d.read()?,
+            dotrec_len: d.read()?,
+            dotname_len: d.read()?,
+            dotfile_type: d.flags()?,
+            dotname: d.read()?,
+            dotdotinode: d.read()?,
+            dotdotrec_len: d.read()?,
+            dotdotname_len: d.read()?,
+            dotdotfile_type: d.flags()?,
+            dotdotname: d.read()?,
+            reserved_zero: d.read()?,
+            hash_version: d.read()?,
+            info_length: d.read()?,
+            indirect_levels: d.read()?,
+            unused_flags: d.read()?,
+            limit: d.read()?,
+            count: d.read()?,
+            block: d.read()?,
+        })
+    }
+}
+impl LoadAble for DxNode {
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        Ok(DxNode {
+            fake_inode: d.read()?,
+            fake_rec_len: d.read()?,
+            name_len: d.read()?,
+            file_type: d.flags()?,
+            limit: d.read()?,
+            count: d.read()?,
+            block: d.read()?,
+        })
+    }
+}
@@ -1,4 +1,5 @@
-use super::LoadAble;
+use super::{Decoder, LoadAble};
+use crate::error::Result;
 
 #[allow(dead_code)]
 #[derive(Debug)]
@@ -24,7 +25,17 @@ pub struct ExtentHeader {
     /// Generation of the tree. (Used by Lustre, but not standard ext4).
     pub eh_generation: u32,
 }
-impl LoadAble for ExtentHeader {}
+impl LoadAble for ExtentHeader {
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        Ok(ExtentHeader {
+            eh_magic: d.read()?,
+            eh_entries: d.read()?,
+            eh_max: d.read()?,
+            eh_depth: d.read()?,
+            eh_generation: d.read()?,
+        })
+    }
+}
 
 #[allow(dead_code)]
 #[derive(Debug)]
@@ -43,7 +54,16 @@ pub struct ExtentIdx {
 
     pub ei_unused: u16,
 }
-impl LoadAble for ExtentIdx {}
+impl LoadAble for ExtentIdx {
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        Ok(ExtentIdx {
+            ei_block: d.read()?,
+            ei_leaf_lo: d.read()?,
+            ei_leaf_hi: d.read()?,
+            ei_unused: d.read()?,
+        })
+    }
+}
 
 // Leaf node
 #[allow(dead_code)]
@@ -67,7 +87,49 @@ pub struct Extent {
     /// Lower 32-bits of the block number to which this extent points.
     pub ee_start_lo: u32,
 }
-impl LoadAble for Extent {}
+impl LoadAble for Extent {
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        Ok(Extent {
+            ee_block: d.read()?,
+            ee_len: d.read()?,
+            ee_start_hi: d.read()?,
+            ee_start_lo: d.read()?,
+        })
+    }
+}
+impl Extent {
+    /// Number of blocks covered by this extent.
+    pub fn len(&self) -> u32 {
+        if self.ee_len > 32768 {
+            self.ee_len as u32 - 32768
+        } else {
+            self.ee_len as u32
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Uninitialized extents are allocated but read back as zeros.
+    pub fn is_uninit(&self) -> bool {
+        self.ee_len > 32768
+    }
+
+    /// Physical block number of the first block covered by this extent.
+    pub fn start(&self) -> u64 {
+        ((self.ee_start_hi as u64) << 32) | self.ee_start_lo as u64
+    }
+
+    /// Physical block backing the logical block `lblk`, if it falls inside this extent.
+    pub fn map(&self, lblk: u32) -> Option<u64> {
+        if lblk >= self.ee_block && lblk - self.ee_block < self.len() {
+            Some(self.start() + (lblk - self.ee_block) as u64)
+        } else {
+            None
+        }
+    }
+}
 
 #[allow(dead_code)]
 #[derive(Debug)]
@@ -76,4 +138,10 @@ pub struct ExtentTail {
     /// Checksum of the extent block, crc32c(uuid+inum+igeneration+extentblock)
     pub et_checksum: u32,
 }
-impl LoadAble for ExtentTail {}
+impl LoadAble for ExtentTail {
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        Ok(ExtentTail {
+            et_checksum: d.read()?,
+        })
+    }
+}
@@ -1,6 +1,7 @@
 use bitflags::bitflags;
 
-use super::LoadAble;
+use super::{Decoder, LoadAble};
+use crate::error::{Ext4Error, Result};
 
 bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -94,4 +95,120 @@ pub struct GroupDesc {
     pub bg_reserved: u32,
 }
 
-impl LoadAble for GroupDesc {}
+impl LoadAble for GroupDesc {
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        Ok(GroupDesc {
+            bg_block_bitmap_lo: d.read()?,
+            bg_inode_bitmap_lo: d.read()?,
+            bg_inode_table_lo: d.read()?,
+            bg_free_blocks_count_lo: d.read()?,
+            bg_free_inodes_count_lo: d.read()?,
+            bg_used_dirs_count_lo: d.read()?,
+            bg_flags: d.flags()?,
+            bg_exclude_bitmap_lo: d.read()?,
+            bg_block_bitmap_csum_lo: d.read()?,
+            bg_inode_bitmap_csum_lo: d.read()?,
+            bg_itable_unused_lo: d.read()?,
+            bg_checksum: d.read()?,
+            bg_block_bitmap_hi: d.read()?,
+            bg_inode_bitmap_hi: d.read()?,
+            bg_inode_table_hi: d.read()?,
+            bg_free_blocks_count_hi: d.read()?,
+            bg_free_inodes_count_hi: d.read()?,
+            bg_used_dirs_count_hi: d.read()?,
+            bg_itable_unused_hi: d.read()?,
+            bg_exclude_bitmap_hi: d.read()?,
+            bg_block_bitmap_csum_hi: d.read()?,
+            bg_inode_bitmap_csum_hi: d.read()?,
+            bg_reserved: d.read()?,
+        })
+    }
+}
+impl GroupDesc {
+    /// Reads a descriptor that is `desc_size` bytes long on disk.
+    /// Without the 64bit feature descriptors are 32 bytes and the upper halves are zero.
+    pub fn from_desc(buf: &[u8], offset: usize, desc_size: usize) -> Result<Self> {
+        let mut bytes = [0u8; std::mem::size_of::<Self>()];
+        let len = desc_size.min(bytes.len());
+        let desc = buf.get(offset..offset + len).ok_or(Ext4Error::Truncated {
+            structure: "GroupDesc",
+            offset,
+        })?;
+        bytes[..len].copy_from_slice(desc);
+        Self::from_buffer(&bytes, 0)
+    }
+
+    /// Location of the block bitmap.
+    pub fn block_bitmap(&self) -> u64 {
+        ((self.bg_block_bitmap_hi as u64) << 32) | self.bg_block_bitmap_lo as u64
+    }
+
+    /// Location of the inode bitmap.
+    pub fn inode_bitmap(&self) -> u64 {
+        ((self.bg_inode_bitmap_hi as u64) << 32) | self.bg_inode_bitmap_lo as u64
+    }
+
+    /// Location of the inode table.
+    pub fn inode_table(&self) -> u64 {
+        ((self.bg_inode_table_hi as u64) << 32) | self.bg_inode_table_lo as u64
+    }
+
+    /// Free block count.
+    pub fn free_blocks_count(&self) -> u32 {
+        ((self.bg_free_blocks_count_hi as u32) << 16) | self.bg_free_blocks_count_lo as u32
+    }
+
+    /// Free inode count.
+    pub fn free_inodes_count(&self) -> u32 {
+        ((self.bg_free_inodes_count_hi as u32) << 16) | self.bg_free_inodes_count_lo as u32
+    }
+
+    /// Directory count.
+    pub fn used_dirs_count(&self) -> u32 {
+        ((self.bg_used_dirs_count_hi as u32) << 16) | self.bg_used_dirs_count_lo as u32
+    }
+
+    /// Unused inode count.
+    pub fn itable_unused(&self) -> u32 {
+        ((self.bg_itable_unused_hi as u32) << 16) | self.bg_itable_unused_lo as u32
+    }
+
+    /// Checksum of the block bitmap. Only the low 16 bits are stored in 32-byte descriptors.
+    pub fn block_bitmap_csum(&self) -> u32 {
+        ((self.bg_block_bitmap_csum_hi as u32) << 16) | self.bg_block_bitmap_csum_lo as u32
+    }
+
+    /// Checksum of the inode bitmap. Only the low 16 bits are stored in 32-byte descriptors.
+    pub fn inode_bitmap_csum(&self) -> u32 {
+        ((self.bg_inode_bitmap_csum_hi as u32) << 16) | self.bg_inode_bitmap_csum_lo as u32
+    }
+}
+
+/// All group descriptors of the filesystem, indexed by group number.
+#[derive(Debug, Default)]
+pub struct GroupDescTable {
+    pub descs: Vec<GroupDesc>,
+}
+
+impl GroupDescTable {
+    /// Parses `count` descriptors laid out back to back every `desc_size` bytes.
+    pub fn from_buffer(buf: &[u8], desc_size: usize, count: u32) -> Result<Self> {
+        Ok(GroupDescTable {
+            descs: (0..count as usize)
+                .map(|i| GroupDesc::from_desc(buf, i * desc_size, desc_size))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    pub fn get(&self, group_num: u32) -> Option<&GroupDesc> {
+        self.descs.get(group_num as usize)
+    }
+
+    pub fn len(&self) -> usize {
+        self.descs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.descs.is_empty()
+    }
+}
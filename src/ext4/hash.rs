@@ -0,0 +1,332 @@
+use super::superblock::DxHash;
+
+/// Seed used when the superblock's s_hash_seed is all zeros.
+const DEFAULT_SEED: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+
+/// Hash value the kernel reserves as the end-of-directory marker.
+const HTREE_EOF_32BIT: u32 = 0x7fffffff;
+
+/// Computes the (major, minor) htree hash of `name`.
+/// Returns `None` for algorithms that are not supported (SipHash, used by
+/// encrypted casefolded directories).
+pub fn dx_hash(name: &[u8], version: DxHash, seed: &[u32; 4]) -> Option<(u32, u32)> {
+    let mut buf = if seed.iter().any(|&s| s != 0) {
+        *seed
+    } else {
+        DEFAULT_SEED
+    };
+
+    let (hash, minor_hash) = if version == DxHash::LEGACY {
+        (dx_hack_hash(name, true), 0)
+    } else if version == DxHash::LEGACY_UNSIGNED {
+        (dx_hack_hash(name, false), 0)
+    } else if version == DxHash::HALF_MD4 || version == DxHash::HALF_MD4_UNSIGNED {
+        let signed = version == DxHash::HALF_MD4;
+        for chunk in chunks(name, 32) {
+            let input = str2hashbuf::<8>(chunk, signed);
+            half_md4_transform(&mut buf, &input);
+        }
+        (buf[1], buf[2])
+    } else if version == DxHash::TEA || version == DxHash::TEA_UNSIGNED {
+        let signed = version == DxHash::TEA;
+        for chunk in chunks(name, 16) {
+            let input = str2hashbuf::<4>(chunk, signed);
+            tea_transform(&mut buf, &input);
+        }
+        (buf[0], buf[1])
+    } else {
+        return None;
+    };
+
+    let mut hash = hash & !1;
+    if hash == HTREE_EOF_32BIT << 1 {
+        hash = (HTREE_EOF_32BIT - 1) << 1;
+    }
+    Some((hash, minor_hash))
+}
+
+/// Splits `name` into the windows the block hashes consume. Each window still
+/// sees the remaining length, which feeds the padding, so the slices run to the end.
+fn chunks(name: &[u8], step: usize) -> impl Iterator<Item = &[u8]> {
+    (0..name.len()).step_by(step).map(move |i| &name[i..])
+}
+
+/// Widens a name byte the way the kernel does for signed or unsigned `char`.
+fn char_value(c: u8, signed: bool) -> u32 {
+    if signed {
+        c as i8 as i32 as u32
+    } else {
+        c as u32
+    }
+}
+
+/// The original ext3 directory hash.
+fn dx_hack_hash(name: &[u8], signed: bool) -> u32 {
+    let mut hash0: u32 = 0x12a3fe2d;
+    let mut hash1: u32 = 0x37abe8f9;
+
+    for &c in name {
+        let mut hash = hash1.wrapping_add(hash0 ^ char_value(c, signed).wrapping_mul(7152373));
+        if hash & 0x80000000 != 0 {
+            hash = hash.wrapping_sub(0x7fffffff);
+        }
+        hash1 = hash0;
+        hash0 = hash;
+    }
+    hash0 << 1
+}
+
+/// Packs up to `N * 4` bytes of `msg` into hash input words, padded with the length.
+fn str2hashbuf<const N: usize>(msg: &[u8], signed: bool) -> [u32; N] {
+    let len = msg.len() as u32;
+    let mut pad = len | (len << 8);
+    pad |= pad << 16;
+
+    let mut out = [pad; N];
+    let mut val = pad;
+    let mut idx = 0;
+    for (i, &c) in msg.iter().take(N * 4).enumerate() {
+        val = char_value(c, signed).wrapping_add(val << 8);
+        if i % 4 == 3 {
+            out[idx] = val;
+            idx += 1;
+            val = pad;
+        }
+    }
+    if idx < N {
+        out[idx] = val;
+    }
+    out
+}
+
+fn tea_transform(buf: &mut [u32; 4], input: &[u32; 4]) {
+    const DELTA: u32 = 0x9E3779B9;
+    let (mut b0, mut b1) = (buf[0], buf[1]);
+    let [a, b, c, d] = *input;
+    let mut sum: u32 = 0;
+
+    for _ in 0..16 {
+        sum = sum.wrapping_add(DELTA);
+        b0 = b0.wrapping_add(
+            ((b1 << 4).wrapping_add(a)) ^ (b1.wrapping_add(sum)) ^ ((b1 >> 5).wrapping_add(b)),
+        );
+        b1 = b1.wrapping_add(
+            ((b0 << 4).wrapping_add(c)) ^ (b0.wrapping_add(sum)) ^ ((b0 >> 5).wrapping_add(d)),
+        );
+    }
+    buf[0] = buf[0].wrapping_add(b0);
+    buf[1] = buf[1].wrapping_add(b1);
+}
+
+fn half_md4_transform(buf: &mut [u32; 4], input: &[u32; 8]) {
+    const K1: u32 = 0;
+    const K2: u32 = 0o13240474631;
+    const K3: u32 = 0o15666365641;
+
+    fn f(x: u32, y: u32, z: u32) -> u32 {
+        z ^ (x & (y ^ z))
+    }
+    fn g(x: u32, y: u32, z: u32) -> u32 {
+        (x & y).wrapping_add((x ^ y) & z)
+    }
+    fn h(x: u32, y: u32, z: u32) -> u32 {
+        x ^ y ^ z
+    }
+    fn round(
+        func: fn(u32, u32, u32) -> u32,
+        a: u32,
+        b: u32,
+        c: u32,
+        d: u32,
+        x: u32,
+        s: u32,
+    ) -> u32 {
+        a.wrapping_add(func(b, c, d)).wrapping_add(x).rotate_left(s)
+    }
+
+    let [mut a, mut b, mut c, mut d] = *buf;
+
+    // Round 1
+    a = round(f, a, b, c, d, input[0].wrapping_add(K1), 3);
+    d = round(f, d, a, b, c, input[1].wrapping_add(K1), 7);
+    c = round(f, c, d, a, b, input[2].wrapping_add(K1), 11);
+    b = round(f, b, c, d, a, input[3].wrapping_add(K1), 19);
+    a = round(f, a, b, c, d, input[4].wrapping_add(K1), 3);
+    d = round(f, d, a, b, c, input[5].wrapping_add(K1), 7);
+    c = round(f, c, d, a, b, input[6].wrapping_add(K1), 11);
+    b = round(f, b, c, d, a, input[7].wrapping_add(K1), 19);
+
+    // Round 2
+    a = round(g, a, b, c, d, input[1].wrapping_add(K2), 3);
+    d = round(g, d, a, b, c, input[3].wrapping_add(K2), 5);
+    c = round(g, c, d, a, b, input[5].wrapping_add(K2), 9);
+    b = round(g, b, c, d, a, input[7].wrapping_add(K2), 13);
+    a = round(g, a, b, c, d, input[0].wrapping_add(K2), 3);
+    d = round(g, d, a, b, c, input[2].wrapping_add(K2), 5);
+    c = round(g, c, d, a, b, input[4].wrapping_add(K2), 9);
+    b = round(g, b, c, d, a, input[6].wrapping_add(K2), 13);
+
+    // Round 3
+    a = round(h, a, b, c, d, input[3].wrapping_add(K3), 3);
+    d = round(h, d, a, b, c, input[7].wrapping_add(K3), 9);
+    c = round(h, c, d, a, b, input[2].wrapping_add(K3), 11);
+    b = round(h, b, c, d, a, input[6].wrapping_add(K3), 15);
+    a = round(h, a, b, c, d, input[1].wrapping_add(K3), 3);
+    d = round(h, d, a, b, c, input[5].wrapping_add(K3), 9);
+    c = round(h, c, d, a, b, input[0].wrapping_add(K3), 11);
+    b = round(h, b, c, d, a, input[4].wrapping_add(K3), 15);
+
+    buf[0] = buf[0].wrapping_add(a);
+    buf[1] = buf[1].wrapping_add(b);
+    buf[2] = buf[2].wrapping_add(c);
+    buf[3] = buf[3].wrapping_add(d);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// s_hash_seed of a filesystem with hash seed c63bddf9-490a-4bb5-bee4-097b2099d115.
+    const SEED: [u32; 4] = [0xf9dd3bc6, 0xb54b0a49, 0x7b09e4be, 0x15d19920];
+
+    // Expected (major, minor) hashes from e2fsprogs' `debugfs dx_hash`. The
+    // names exercise the signed and unsigned char handling and span more
+    // than one TEA and half-MD4 block.
+    const SEEDED: &[(DxHash, &[u8], u32, u32)] = &[
+        (DxHash::LEGACY, b"lost+found", 0x5e2aba24, 0x00000000),
+        (DxHash::LEGACY, b"caf\xe9", 0x65f23bce, 0x00000000),
+        (
+            DxHash::LEGACY,
+            b"\xff\xfe\x80-is-a-name-longer-than-thirty-two-bytes",
+            0x15c95a3a,
+            0x00000000,
+        ),
+        (DxHash::HALF_MD4, b"lost+found", 0xd6b34d7c, 0x03510a01),
+        (DxHash::HALF_MD4, b"caf\xe9", 0xeeecc756, 0x90bfea87),
+        (
+            DxHash::HALF_MD4,
+            b"\xff\xfe\x80-is-a-name-longer-than-thirty-two-bytes",
+            0xa94849c6,
+            0x8be50dc6,
+        ),
+        (DxHash::TEA, b"lost+found", 0x042d4934, 0xd36951a1),
+        (DxHash::TEA, b"caf\xe9", 0x8340b1e8, 0x0fbb6366),
+        (
+            DxHash::TEA,
+            b"\xff\xfe\x80-is-a-name-longer-than-thirty-two-bytes",
+            0x287f81c2,
+            0xd92017aa,
+        ),
+        (
+            DxHash::LEGACY_UNSIGNED,
+            b"lost+found",
+            0x5e2aba24,
+            0x00000000,
+        ),
+        (DxHash::LEGACY_UNSIGNED, b"caf\xe9", 0x7c3849d0, 0x00000000),
+        (
+            DxHash::LEGACY_UNSIGNED,
+            b"\xff\xfe\x80-is-a-name-longer-than-thirty-two-bytes",
+            0x984467ae,
+            0x00000000,
+        ),
+        (
+            DxHash::HALF_MD4_UNSIGNED,
+            b"lost+found",
+            0xd6b34d7c,
+            0x03510a01,
+        ),
+        (
+            DxHash::HALF_MD4_UNSIGNED,
+            b"caf\xe9",
+            0x55779f4e,
+            0xf5a09285,
+        ),
+        (
+            DxHash::HALF_MD4_UNSIGNED,
+            b"\xff\xfe\x80-is-a-name-longer-than-thirty-two-bytes",
+            0x27ea3d72,
+            0x21f1dac5,
+        ),
+        (DxHash::TEA_UNSIGNED, b"lost+found", 0x042d4934, 0xd36951a1),
+        (DxHash::TEA_UNSIGNED, b"caf\xe9", 0xf378a442, 0xce97946b),
+        (
+            DxHash::TEA_UNSIGNED,
+            b"\xff\xfe\x80-is-a-name-longer-than-thirty-two-bytes",
+            0x5fca7ed8,
+            0xa37c2f05,
+        ),
+    ];
+
+    const ZERO_SEED: &[(DxHash, &[u8], u32, u32)] = &[
+        (DxHash::HALF_MD4, b"lost+found", 0x591de422, 0x6ffc56e0),
+        (DxHash::HALF_MD4, b"caf\xe9", 0x9be4a372, 0xc33d4f19),
+        (
+            DxHash::HALF_MD4,
+            b"\xff\xfe\x80-is-a-name-longer-than-thirty-two-bytes",
+            0x00cd1a26,
+            0x047c2fc1,
+        ),
+        (DxHash::TEA, b"lost+found", 0x2dbf9e80, 0xbfebee4f),
+        (DxHash::TEA, b"caf\xe9", 0x84b3a194, 0x1cf71779),
+        (
+            DxHash::TEA,
+            b"\xff\xfe\x80-is-a-name-longer-than-thirty-two-bytes",
+            0xa24ec3ac,
+            0xcc452302,
+        ),
+        (
+            DxHash::HALF_MD4_UNSIGNED,
+            b"lost+found",
+            0x591de422,
+            0x6ffc56e0,
+        ),
+        (
+            DxHash::HALF_MD4_UNSIGNED,
+            b"caf\xe9",
+            0xab408964,
+            0x07893b5c,
+        ),
+        (
+            DxHash::HALF_MD4_UNSIGNED,
+            b"\xff\xfe\x80-is-a-name-longer-than-thirty-two-bytes",
+            0x877d9d8a,
+            0x857e5ca6,
+        ),
+        (DxHash::TEA_UNSIGNED, b"lost+found", 0x2dbf9e80, 0xbfebee4f),
+        (DxHash::TEA_UNSIGNED, b"caf\xe9", 0xe665cc26, 0x417d943d),
+        (
+            DxHash::TEA_UNSIGNED,
+            b"\xff\xfe\x80-is-a-name-longer-than-thirty-two-bytes",
+            0xaa991dca,
+            0x94f1f041,
+        ),
+    ];
+
+    fn check(vectors: &[(DxHash, &[u8], u32, u32)], seed: &[u32; 4]) {
+        for &(version, name, major, minor) in vectors {
+            assert_eq!(
+                dx_hash(name, version, seed),
+                Some((major, minor)),
+                "{:?} of {:?}",
+                version,
+                String::from_utf8_lossy(name)
+            );
+        }
+    }
+
+    #[test]
+    fn matches_e2fsprogs() {
+        check(SEEDED, &SEED);
+    }
+
+    #[test]
+    fn zero_seed_falls_back_to_default() {
+        check(ZERO_SEED, &[0; 4]);
+    }
+
+    #[test]
+    fn siphash_is_unsupported() {
+        assert_eq!(dx_hash(b"lost+found", DxHash::SIPHASH, &SEED), None);
+    }
+}
@@ -1,5 +1,10 @@
-use super::LoadAble;
+use super::directories::FileType;
+use super::superblock::{ROCompatibleFeatures, SuperBlock};
+use super::{Decoder, LoadAble};
+use crate::error::Result;
 use bitflags::bitflags;
+use std::mem::offset_of;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 bitflags! {
     #[derive(Debug)]
@@ -237,4 +242,174 @@ pub struct Inode {
     pub i_projid: u32,
 }
 
-impl LoadAble for Inode {}
+impl LoadAble for Inode {
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        Ok(Inode {
+            i_mode: d.flags()?,
+            i_uid: d.read()?,
+            i_size_lo: d.read()?,
+            i_atime: d.read()?,
+            i_ctime: d.read()?,
+            i_mtime: d.read()?,
+            i_dtime: d.read()?,
+            i_gid: d.read()?,
+            i_links_count: d.read()?,
+            i_blocks_lo: d.read()?,
+            i_flags: d.flags()?,
+            l_i_version: d.read()?,
+            i_block: d.read()?,
+            i_generation: d.read()?,
+            i_file_acl_lo: d.read()?,
+            i_size_high: d.read()?,
+            i_obso_faddr: d.read()?,
+            l_i_blocks_high: d.read()?,
+            l_i_file_acl_high: d.read()?,
+            l_i_uid_high: d.read()?,
+            l_i_gid_high: d.read()?,
+            l_i_checksum_lo: d.read()?,
+            l_i_reserved: d.read()?,
+            i_extra_isize: d.read()?,
+            i_checksum_hi: d.read()?,
+            i_ctime_extra: d.read()?,
+            i_mtime_extra: d.read()?,
+            i_atime_extra: d.read()?,
+            i_crtime: d.read()?,
+            i_crtime_extra: d.read()?,
+            i_version_hi: d.read()?,
+            i_projid: d.read()?,
+        })
+    }
+}
+
+impl Inode {
+    /// Decodes an on-disk inode record of any size. Only the first 128 bytes
+    /// are always present; later fields exist only as far as i_extra_isize
+    /// reaches and are left zero otherwise.
+    pub fn from_raw(raw: &[u8]) -> Result<Self> {
+        let mut bytes = [0u8; std::mem::size_of::<Self>()];
+        let base = raw.len().min(128);
+        bytes[..base].copy_from_slice(&raw[..base]);
+
+        if raw.len() >= 130 {
+            let extra_isize = u16::from_le_bytes([raw[128], raw[129]]) as usize;
+            // An i_extra_isize that overruns the record or is misaligned is corrupt;
+            // treat the inode as having no extra fields.
+            if extra_isize.is_multiple_of(4) && 128 + extra_isize <= raw.len() {
+                let end = (128 + extra_isize).min(bytes.len());
+                bytes[128..end].copy_from_slice(&raw[128..end]);
+            }
+        }
+        Self::from_buffer(&bytes, 0)
+    }
+
+    /// Offset of the in-inode extended attribute space within the inode record.
+    pub fn xattr_area_offset(&self) -> usize {
+        128 + self.i_extra_isize as usize
+    }
+
+    /// File size in bytes.
+    pub fn size(&self) -> u64 {
+        ((self.i_size_high as u64) << 32) | self.i_size_lo as u64
+    }
+
+    /// Owner UID.
+    pub fn uid(&self) -> u32 {
+        ((self.l_i_uid_high as u32) << 16) | self.i_uid as u32
+    }
+
+    /// Owner GID.
+    pub fn gid(&self) -> u32 {
+        ((self.l_i_gid_high as u32) << 16) | self.i_gid as u32
+    }
+
+    /// Bytes of disk space used by the file, including metadata blocks.
+    pub fn blocks_bytes(&self, sb: &SuperBlock) -> u64 {
+        if !sb
+            .s_feature_ro_compat
+            .contains(ROCompatibleFeatures::HUGE_FILE)
+        {
+            return self.i_blocks_lo as u64 * 512;
+        }
+
+        let blocks = ((self.l_i_blocks_high as u64) << 32) | self.i_blocks_lo as u64;
+        if self.i_flags.contains(IFlags::Ext4HugeFileFl) {
+            blocks << (10 + sb.s_log_block_size)
+        } else {
+            blocks * 512
+        }
+    }
+
+    /// Type of the file, from the format bits of i_mode.
+    pub fn file_type(&self) -> FileType {
+        match self.i_mode.bits() & 0xF000 {
+            0x1000 => FileType::Ext4FtFifo,
+            0x2000 => FileType::Ext4FtChrdev,
+            0x4000 => FileType::Ext4FtDir,
+            0x6000 => FileType::Ext4FtBlkdev,
+            0x8000 => FileType::Ext4FtRegFile,
+            0xA000 => FileType::Ext4FtSymlink,
+            0xC000 => FileType::Ext4FtSock,
+            _ => FileType::Ext4FtUnknown,
+        }
+    }
+
+    /// Whether the field ending at byte `end` of the inode is present,
+    /// i.e. covered by i_extra_isize.
+    fn has_extra_field(&self, end: usize) -> bool {
+        128 + self.i_extra_isize as usize >= end
+    }
+
+    /// Decodes a timestamp whose low 32 bits are signed seconds since the epoch.
+    /// The extra field, when present, holds two more epoch bits and the nanoseconds.
+    fn decode_time(seconds: u32, extra: Option<u32>) -> SystemTime {
+        let extra = extra.unwrap_or(0);
+        let secs = seconds as i32 as i64 + (((extra & 0x3) as i64) << 32);
+        let nanos = extra >> 2;
+        if secs >= 0 {
+            UNIX_EPOCH + Duration::new(secs as u64, nanos)
+        } else {
+            UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs())
+                + Duration::from_nanos(nanos as u64)
+        }
+    }
+
+    /// Last access time.
+    pub fn atime(&self) -> SystemTime {
+        let extra = self
+            .has_extra_field(offset_of!(Inode, i_atime_extra) + 4)
+            .then_some(self.i_atime_extra);
+        Self::decode_time(self.i_atime, extra)
+    }
+
+    /// Last inode change time.
+    pub fn ctime(&self) -> SystemTime {
+        let extra = self
+            .has_extra_field(offset_of!(Inode, i_ctime_extra) + 4)
+            .then_some(self.i_ctime_extra);
+        Self::decode_time(self.i_ctime, extra)
+    }
+
+    /// Last data modification time.
+    pub fn mtime(&self) -> SystemTime {
+        let extra = self
+            .has_extra_field(offset_of!(Inode, i_mtime_extra) + 4)
+            .then_some(self.i_mtime_extra);
+        Self::decode_time(self.i_mtime, extra)
+    }
+
+    /// File creation time, only recorded in large inodes.
+    pub fn crtime(&self) -> Option<SystemTime> {
+        if !self.has_extra_field(offset_of!(Inode, i_crtime) + 4) {
+            return None;
+        }
+        let extra = self
+            .has_extra_field(offset_of!(Inode, i_crtime_extra) + 4)
+            .then_some(self.i_crtime_extra);
+        Some(Self::decode_time(self.i_crtime, extra))
+    }
+
+    /// Deletion time, or `None` if the inode has not been deleted.
+    pub fn dtime(&self) -> Option<SystemTime> {
+        (self.i_dtime != 0).then(|| Self::decode_time(self.i_dtime, None))
+    }
+}
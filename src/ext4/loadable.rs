@@ -1,39 +1,104 @@
-use libc::memcpy;
-use std::fs::File;
-use std::io::{Read, Seek, SeekFrom};
 use std::mem::size_of;
-use std::os::raw::c_void;
 
-pub trait LoadAble: Sized {
-    fn from_file_offset(file: &mut File, offset: u64) -> std::io::Result<Self> {
-        let mut bytes = vec![0u8; size_of::<Self>()];
+use bitflags::Flags;
 
-        file.seek(SeekFrom::Start(offset))?;
-        file.read_exact(&mut bytes[..])?;
+use crate::device::BlockDevice;
+use crate::error::{Ext4Error, Result};
 
-        let size = size_of::<Self>();
-        let mut result = unsafe { std::mem::MaybeUninit::<Self>::uninit().assume_init() };
+/// Little-endian cursor over the bytes of an on-disk structure.
+/// Every read is checked against the end of the buffer.
+pub struct Decoder<'a> {
+    buf: &'a [u8],
+    start: usize,
+    pos: usize,
+    structure: &'static str,
+}
 
-        unsafe {
-            memcpy(
-                &mut result as *mut Self as *mut c_void,
-                bytes.as_ptr() as *const c_void,
-                size,
-            );
+impl<'a> Decoder<'a> {
+    /// Starts decoding `structure` at `offset` of `buf`.
+    pub fn new(buf: &'a [u8], offset: usize, structure: &'static str) -> Self {
+        Decoder {
+            buf,
+            start: offset,
+            pos: offset,
+            structure,
         }
-        Ok(result)
     }
 
-    fn from_buffer(buf: &Vec<u8>, offset: usize) -> Self {
-        let size = size_of::<Self>();
-        let mut result = unsafe { std::mem::MaybeUninit::<Self>::uninit().assume_init() };
-        unsafe {
-            memcpy(
-                &mut result as *mut Self as *mut c_void,
-                buf[offset..].as_ptr() as *const c_void,
-                size,
-            );
+    /// The next `len` bytes.
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.buf.len())
+            .ok_or(Ext4Error::Truncated {
+                structure: self.structure,
+                offset: self.start,
+            })?;
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    /// Decodes the next field.
+    pub fn read<T: Decode>(&mut self) -> Result<T> {
+        T::decode(self)
+    }
+
+    /// Decodes a bitflags field, keeping bits that have no name.
+    pub fn flags<F: Flags>(&mut self) -> Result<F>
+    where
+        F::Bits: Decode,
+    {
+        Ok(F::from_bits_retain(self.read()?))
+    }
+}
+
+/// A value with a fixed little-endian encoding.
+pub trait Decode: Sized {
+    fn decode(d: &mut Decoder) -> Result<Self>;
+}
+
+macro_rules! decode_le {
+    ($($t:ty),*) => {
+        $(
+            impl Decode for $t {
+                fn decode(d: &mut Decoder) -> Result<Self> {
+                    let mut raw = [0u8; size_of::<$t>()];
+                    raw.copy_from_slice(d.bytes(size_of::<$t>())?);
+                    Ok(<$t>::from_le_bytes(raw))
+                }
+            }
+        )*
+    };
+}
+
+decode_le!(u8, u16, u32, u64);
+
+impl<T: Decode + Copy + Default, const N: usize> Decode for [T; N] {
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        let mut out = [T::default(); N];
+        for item in out.iter_mut() {
+            *item = d.read()?;
         }
-        result
+        Ok(out)
+    }
+}
+
+pub trait LoadAble: Sized {
+    /// Decodes the structure field by field from the decoder's position.
+    fn decode(d: &mut Decoder) -> Result<Self>;
+
+    fn from_device_offset<D: BlockDevice + ?Sized>(device: &mut D, offset: u64) -> Result<Self> {
+        let mut bytes = vec![0u8; size_of::<Self>()];
+        device.read_at(offset, &mut bytes)?;
+
+        Self::from_buffer(&bytes, 0)
+    }
+
+    fn from_buffer(buf: &[u8], offset: usize) -> Result<Self> {
+        let name = std::any::type_name::<Self>();
+        let name = name.rsplit("::").next().unwrap_or(name);
+        Self::decode(&mut Decoder::new(buf, offset, name))
     }
 }
@@ -1,17 +1,35 @@
+mod acl;
+mod bitmap;
+mod csum;
 mod directories;
 mod extents;
 mod group_desc;
+mod hash;
 mod inode;
 mod loadable;
 mod superblock;
+mod xattr;
 
 pub mod structs {
-    pub use crate::ext4::group_desc::GroupDesc;
+    pub use crate::ext4::bitmap::Bitmap;
+    pub use crate::ext4::group_desc::{GroupDesc, GroupDescTable};
     pub use crate::ext4::inode::Inode;
     pub use crate::ext4::superblock::SuperBlock;
     pub mod dir {
         pub use crate::ext4::directories::{
-            DirEntry as Entry, DirEntry2 as Entry2, DirEntryHash as EntryHash, DxEntry, DxRoot,
+            DirEntry as Entry, DirEntry2 as Entry2, DirEntryHash as EntryHash, DxEntry, DxNode,
+            DxRoot,
+        };
+    }
+    pub mod acl {
+        pub use crate::ext4::acl::{
+            Acl, AclEntry as Entry, AclEntryLong as EntryLong, AclEntryShort as EntryShort,
+            AclHeader as Header, AclTag as Tag, ACL_VERSION as VERSION,
+        };
+    }
+    pub mod xattr {
+        pub use crate::ext4::xattr::{
+            split_name, Xattr, XattrEntry as Entry, XattrHeader as Header, XATTR_MAGIC as MAGIC,
         };
     }
     pub mod extent {
@@ -21,10 +39,19 @@ pub mod structs {
     }
 }
 
+pub mod checksum {
+    pub use crate::ext4::csum::{
+        bitmap_csum, crc16, crc32c, dir_block_csum, dx_node_csum, dx_tail_offset,
+        extent_block_csum, fs_seed, group_desc_crc16, group_desc_csum, inode_csum,
+        inode_has_csum_hi, inode_seed, super_block_csum, xattr_block_csum, ChecksumMismatch,
+        Checksummed, DIR_TAIL_SIZE,
+    };
+}
+
 pub mod flags {
     pub mod superblock {
         pub use crate::ext4::superblock::{
-            CompatibleFeatures, Ext4Defm, IncompatibleFeatures, ROCompatibleFeatures, OS,
+            CompatibleFeatures, DxHash, Ext4Defm, IncompatibleFeatures, ROCompatibleFeatures, OS,
         };
     }
     pub mod group_desc {
@@ -36,6 +63,10 @@ pub mod flags {
     pub mod dir {
         pub use crate::ext4::directories::FileType;
     }
+    pub mod acl {
+        pub use crate::ext4::acl::AclPerm;
+    }
 }
 
-pub use self::loadable::LoadAble;
+pub use self::hash::dx_hash;
+pub use self::loadable::{Decode, Decoder, LoadAble};
@@ -1,4 +1,5 @@
-use super::LoadAble;
+use super::{Decoder, LoadAble};
+use crate::error::Result;
 use bitflags::bitflags;
 
 bitflags! {
@@ -342,14 +343,303 @@ pub struct SuperBlock {
 }
 
 impl SuperBlock {
+    /// Total block count.
+    pub fn blocks_count(&self) -> u64 {
+        if self.has_64bit_feature() {
+            ((self.s_blocks_count_hi as u64) << 32) | self.s_blocks_count_lo as u64
+        } else {
+            self.s_blocks_count_lo as u64
+        }
+    }
+
+    /// Time of the last write to the filesystem, in seconds since the epoch.
+    pub fn write_time(&self) -> u64 {
+        ((self.s_wtime_hi as u64) << 32) | self.s_wtime as u64
+    }
+
+    /// Number of block groups. Superblocks whose count does not fit in a
+    /// u32 are rejected when a filesystem is opened.
+    pub fn group_count(&self) -> u32 {
+        (self.blocks_count() - self.s_first_data_block as u64)
+            .div_ceil(self.s_blocks_per_group as u64) as u32
+    }
+
+    pub fn has_64bit_feature(&self) -> bool {
+        self.s_feature_incompat
+            .contains(IncompatibleFeatures::_64BIT)
+    }
+
+    /// Size of a group descriptor on disk, in bytes.
+    pub fn desc_size(&self) -> usize {
+        if self.has_64bit_feature() {
+            self.s_desc_size as usize
+        } else {
+            32
+        }
+    }
+
     pub fn has_sparse_super_feature(&self) -> bool {
         self.s_feature_ro_compat
             .contains(ROCompatibleFeatures::SPARSE_SUPER)
     }
 
+    pub fn has_gdt_csum_feature(&self) -> bool {
+        self.s_feature_ro_compat
+            .contains(ROCompatibleFeatures::GDT_CSUM)
+    }
+
+    pub fn has_metadata_csum_feature(&self) -> bool {
+        self.s_feature_ro_compat
+            .contains(ROCompatibleFeatures::METADATA_CSUM)
+    }
+
+    /// Whether group descriptors are checksummed, by gdt_csum or
+    /// metadata_csum. Only then are their UNINIT flags to be trusted.
+    pub fn has_group_desc_csum(&self) -> bool {
+        self.has_gdt_csum_feature() || self.has_metadata_csum_feature()
+    }
+
+    /// Whether block group `bg_num` holds a copy of the superblock: group 0
+    /// always, then the groups picked by sparse_super or sparse_super2, or
+    /// every group without either.
+    pub fn group_has_super(&self, bg_num: u32) -> bool {
+        if bg_num == 0 {
+            true
+        } else if self
+            .s_feature_compat
+            .contains(CompatibleFeatures::SPARSE_SUPER2)
+        {
+            // At most two backups, in the groups named by s_backup_bgs.
+            let backups = self.s_backup_bgs;
+            backups[0] == bg_num || backups[1] == bg_num
+        } else if bg_num <= 1 || !self.has_sparse_super_feature() {
+            true
+        } else if bg_num & 0x1 == 0 {
+            false
+        } else {
+            fn test_root(mut a: u32, b: u32) -> bool {
+                loop {
+                    if a < b {
+                        return false;
+                    } else if a == b {
+                        return true;
+                    } else if !a.is_multiple_of(b) {
+                        return false;
+                    }
+                    a /= b;
+                }
+            }
+
+            test_root(bg_num, 3) || test_root(bg_num, 5) || test_root(bg_num, 7)
+        }
+    }
+
+    /// Groups holding a copy of the superblock, in ascending order,
+    /// generated directly rather than by testing every group.
+    pub fn super_block_groups(&self) -> Vec<u32> {
+        let count = self.group_count();
+        let mut groups = vec![0];
+        if self
+            .s_feature_compat
+            .contains(CompatibleFeatures::SPARSE_SUPER2)
+        {
+            groups.extend(self.s_backup_bgs.iter().filter(|&&g| g != 0 && g < count));
+        } else if self.has_sparse_super_feature() {
+            groups.push(1);
+            for base in [3_u32, 5, 7] {
+                let mut g = base;
+                while g < count {
+                    groups.push(g);
+                    match g.checked_mul(base) {
+                        Some(next) => g = next,
+                        None => break,
+                    }
+                }
+            }
+        } else {
+            groups.extend(1..count);
+        }
+        groups.retain(|&g| g < count);
+        groups.sort_unstable();
+        groups.dedup();
+        groups
+    }
+
+    /// Size of an on-disk inode record. Revision 0 filesystems predate
+    /// s_inode_size and always use 128 bytes.
+    pub fn inode_size(&self) -> usize {
+        if self.s_rev_level == 0 {
+            128
+        } else {
+            self.s_inode_size as usize
+        }
+    }
+
+    /// Whether htree hashes treat name bytes as unsigned chars.
+    pub fn has_unsigned_hash(&self) -> bool {
+        self.s_flags & 0x2 != 0
+    }
+
     // pub fn has_sparse_super_2_feature(&self) -> bool {
     // self.s_feature_ro_compat
     // }
 }
 
-impl LoadAble for SuperBlock {}
+impl LoadAble for SuperBlock {
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        Ok(SuperBlock {
+            s_inodes_count: d.read()?,
+            s_blocks_count_lo: d.read()?,
+            s_r_blocks_count_lo: d.read()?,
+            s_free_blocks_count_lo: d.read()?,
+            s_free_inodes_count: d.read()?,
+            s_first_data_block: d.read()?,
+            s_log_block_size: d.read()?,
+            s_log_cluster_size: d.read()?,
+            s_blocks_per_group: d.read()?,
+            s_clusters_per_group: d.read()?,
+            s_inodes_per_group: d.read()?,
+            s_mtime: d.read()?,
+            s_wtime: d.read()?,
+            s_mnt_count: d.read()?,
+            s_max_mnt_count: d.read()?,
+            s_magic: d.read()?,
+            s_state: d.read()?,
+            s_errors: d.read()?,
+            s_minor_rev_level: d.read()?,
+            s_lastcheck: d.read()?,
+            s_checkinterval: d.read()?,
+            s_creator_os: d.flags()?,
+            s_rev_level: d.read()?,
+            s_def_resuid: d.read()?,
+            s_def_resgid: d.read()?,
+            s_first_ino: d.read()?,
+            s_inode_size: d.read()?,
+            s_block_group_nr: d.read()?,
+            s_feature_compat: d.flags()?,
+            s_feature_incompat: d.flags()?,
+            s_feature_ro_compat: d.flags()?,
+            s_uuid: d.read()?,
+            s_volume_name: d.read()?,
+            s_last_mounted: d.read()?,
+            s_algorithm_usage_bitmap: d.read()?,
+            s_prealloc_blocks: d.read()?,
+            s_prealloc_dir_blocks: d.read()?,
+            s_reserved_gdt_blocks: d.read()?,
+            s_journal_uuid: d.read()?,
+            s_journal_inum: d.read()?,
+            s_journal_dev: d.read()?,
+            s_last_orphan: d.read()?,
+            s_hash_seed: d.read()?,
+            s_def_hash_version: d.flags()?,
+            s_jnl_backup_type: d.read()?,
+            s_desc_size: d.read()?,
+            s_default_mount_opts: d.flags()?,
+            s_first_meta_bg: d.read()?,
+            s_mkfs_time: d.read()?,
+            s_jnl_blocks: d.read()?,
+            s_blocks_count_hi: d.read()?,
+            s_r_blocks_count_hi: d.read()?,
+            s_free_blocks_count_hi: d.read()?,
+            s_min_extra_isize: d.read()?,
+            s_want_extra_isize: d.read()?,
+            s_flags: d.read()?,
+            s_raid_stride: d.read()?,
+            s_mmp_update_interval: d.read()?,
+            s_mmp_block: d.read()?,
+            s_raid_stripe_width: d.read()?,
+            s_log_groups_per_flex: d.read()?,
+            s_checksum_type: d.read()?,
+            s_encryption_level: d.read()?,
+            s_reserved_pad: d.read()?,
+            s_kbytes_written: d.read()?,
+            s_snapshot_inum: d.read()?,
+            s_snapshot_id: d.read()?,
+            s_snapshot_r_blocks_count: d.read()?,
+            s_snapshot_list: d.read()?,
+            s_error_count: d.read()?,
+            s_first_error_time: d.read()?,
+            s_first_error_ino: d.read()?,
+            s_first_error_block: d.read()?,
+            s_first_error_func: d.read()?,
+            s_first_error_line: d.read()?,
+            s_last_error_time: d.read()?,
+            s_last_error_ino: d.read()?,
+            s_last_error_line: d.read()?,
+            s_last_error_block: d.read()?,
+            s_last_error_func: d.read()?,
+            s_mount_opts: d.read()?,
+            s_usr_quota_inum: d.read()?,
+            s_grp_quota_inum: d.read()?,
+            s_overhead_clusters: d.read()?,
+            s_backup_bgs: d.read()?,
+            s_encrypt_algos: d.read()?,
+            s_encrypt_pw_salt: d.read()?,
+            s_lpf_ino: d.read()?,
+            s_prj_quota_inum: d.read()?,
+            s_checksum_seed: d.read()?,
+            s_wtime_hi: d.read()?,
+            s_mtime_hi: d.read()?,
+            s_mkfs_time_hi: d.read()?,
+            s_lastcheck_hi: d.read()?,
+            s_first_error_time_hi: d.read()?,
+            s_last_error_time_hi: d.read()?,
+            s_first_error_errcode: d.read()?,
+            s_last_error_errcode: d.read()?,
+            s_encoding: d.read()?,
+            s_encoding_flags: d.read()?,
+            s_orphan_file_inum: d.read()?,
+            s_reserved: d.read()?,
+            s_checksum: d.read()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn super_block(
+        groups: u32,
+        compat: CompatibleFeatures,
+        ro: ROCompatibleFeatures,
+    ) -> SuperBlock {
+        let mut sb = SuperBlock::from_buffer(&[0; 1024], 0).unwrap();
+        sb.s_first_data_block = 1;
+        sb.s_blocks_per_group = 8192;
+        sb.s_blocks_count_lo = groups * 8192 + 1;
+        sb.s_feature_compat = compat;
+        sb.s_feature_ro_compat = ro;
+        sb
+    }
+
+    #[test]
+    fn super_block_groups_match_group_has_super() {
+        let sparse = ROCompatibleFeatures::SPARSE_SUPER;
+        let layouts = [
+            (CompatibleFeatures::empty(), ROCompatibleFeatures::empty()),
+            (CompatibleFeatures::empty(), sparse),
+            (CompatibleFeatures::SPARSE_SUPER2, sparse),
+        ];
+        for (compat, ro) in layouts {
+            for groups in [1, 2, 3, 26, 50, 344, 2402] {
+                let mut sb = super_block(groups, compat, ro);
+                sb.s_backup_bgs = [1, groups - 1];
+                let expected: Vec<u32> = (0..groups).filter(|&g| sb.group_has_super(g)).collect();
+                assert_eq!(sb.super_block_groups(), expected, "{} groups", groups);
+            }
+        }
+    }
+
+    #[test]
+    fn super_block_groups_stay_sparse_for_huge_filesystems() {
+        let sb = super_block(
+            u32::MAX / 8192,
+            CompatibleFeatures::empty(),
+            ROCompatibleFeatures::SPARSE_SUPER,
+        );
+        let groups = sb.super_block_groups();
+        assert_eq!(groups[..6], [0, 1, 3, 5, 7, 9]);
+        assert!(groups.len() < 40);
+    }
+}
@@ -0,0 +1,128 @@
+use super::{Decoder, LoadAble};
+use crate::error::Result;
+
+/// Magic number at the start of the in-inode extended attribute area
+/// and of external attribute blocks.
+pub const XATTR_MAGIC: u32 = 0xEA020000;
+
+#[allow(dead_code)]
+#[derive(Debug)]
+#[repr(C)]
+pub struct XattrEntry {
+    /// Length of name.
+    pub e_name_len: u8,
+
+    /// Attribute name index. 7 is "system.", used for inline data.
+    pub e_name_index: u8,
+
+    /// Location of this attribute's value. For in-inode attributes this is
+    /// relative to the first entry.
+    pub e_value_offs: u16,
+
+    /// The inode where the value is stored.
+    /// Zero indicates the value is in the same block as this entry.
+    pub e_value_inum: u32,
+
+    /// Length of attribute value.
+    pub e_value_size: u32,
+
+    /// Hash value of attribute name and attribute value.
+    pub e_hash: u32,
+    // pub e_name: [u8; e_name_len],
+}
+impl LoadAble for XattrEntry {
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        Ok(XattrEntry {
+            e_name_len: d.read()?,
+            e_name_index: d.read()?,
+            e_value_offs: d.read()?,
+            e_value_inum: d.read()?,
+            e_value_size: d.read()?,
+            e_hash: d.read()?,
+        })
+    }
+}
+
+/// Header of an external extended attribute block.
+/// Entries follow immediately after it.
+#[allow(dead_code)]
+#[derive(Debug)]
+#[repr(C)]
+pub struct XattrHeader {
+    /// Magic number for identification, 0xEA020000.
+    pub h_magic: u32,
+
+    /// Reference count. Identical blocks are shared between inodes.
+    pub h_refcount: u32,
+
+    /// Number of disk blocks used. Must be 1.
+    pub h_blocks: u32,
+
+    /// Hash value of all attributes.
+    pub h_hash: u32,
+
+    /// Checksum of the extended attribute block.
+    pub h_checksum: u32,
+
+    /// Zero.
+    pub h_reserved: [u32; 3],
+}
+impl LoadAble for XattrHeader {
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        Ok(XattrHeader {
+            h_magic: d.read()?,
+            h_refcount: d.read()?,
+            h_blocks: d.read()?,
+            h_hash: d.read()?,
+            h_checksum: d.read()?,
+            h_reserved: d.read()?,
+        })
+    }
+}
+
+/// Name prefixes selected by `XattrEntry.e_name_index`.
+/// The POSIX ACL indices map to a full name with an empty suffix.
+const NAME_PREFIXES: [(u8, &str); 7] = [
+    (1, "user."),
+    (2, "system.posix_acl_access"),
+    (3, "system.posix_acl_default"),
+    (4, "trusted."),
+    (6, "security."),
+    (7, "system."),
+    (8, "system.richacl"),
+];
+
+/// A decoded extended attribute.
+#[derive(Debug, Clone)]
+pub struct Xattr {
+    /// Name index selecting the namespace prefix.
+    pub name_index: u8,
+
+    /// Name, without the namespace prefix.
+    pub name: Vec<u8>,
+
+    /// Attribute value.
+    pub value: Vec<u8>,
+}
+
+impl Xattr {
+    /// Full attribute name, such as "user.comment".
+    pub fn full_name(&self) -> String {
+        let prefix = NAME_PREFIXES
+            .iter()
+            .find(|(i, _)| *i == self.name_index)
+            .map(|(_, p)| *p)
+            .unwrap_or("");
+        format!("{}{}", prefix, String::from_utf8_lossy(&self.name))
+    }
+}
+
+/// Splits a full attribute name into its name index and on-disk suffix.
+pub fn split_name(full_name: &str) -> Option<(u8, &[u8])> {
+    // Several prefixes share "system.", so the longest match wins.
+    NAME_PREFIXES
+        .iter()
+        .filter(|(_, p)| full_name.starts_with(p))
+        .max_by_key(|(_, p)| p.len())
+        .map(|(i, p)| (*i, &full_name.as_bytes()[p.len()..]))
+}
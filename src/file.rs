@@ -0,0 +1,126 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::cache::Block;
+use crate::device::BlockDevice;
+use crate::disk::Disk;
+use crate::error::Result;
+use crate::ext4;
+
+/// Read-only handle to the contents of an inode.
+/// Logical offsets are mapped to disk blocks through the inode's extents;
+/// holes and uninitialized extents read back as zeros.
+/// Inline data inodes are served from memory instead.
+pub struct FileHandle<'a, D = std::fs::File> {
+    disk: &'a mut Disk<D>,
+    extents: Vec<ext4::structs::extent::Extent>,
+    inline_data: Option<Vec<u8>>,
+    size: u64,
+    pos: u64,
+}
+
+impl<'a, D: BlockDevice> FileHandle<'a, D> {
+    pub fn new(disk: &'a mut Disk<D>, inode: &ext4::structs::Inode) -> Result<Self> {
+        let extents = disk.get_extents(inode)?;
+        let size = inode.size();
+        Ok(FileHandle {
+            disk,
+            extents,
+            inline_data: None,
+            size,
+            pos: 0,
+        })
+    }
+
+    /// Handle over contents that are stored inside the inode.
+    pub fn from_inline(disk: &'a mut Disk<D>, data: Vec<u8>) -> Self {
+        FileHandle {
+            disk,
+            extents: Vec::new(),
+            size: data.len() as u64,
+            inline_data: Some(data),
+            pos: 0,
+        }
+    }
+
+    /// Size of the file in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Physical block backing the logical block `lblk`, or `None` for a hole.
+    pub fn physical_block(&self, lblk: u32) -> Option<u64> {
+        // Extents are sorted by ee_block, find the last one starting at or before lblk.
+        let idx = self.extents.partition_point(|e| e.ee_block <= lblk);
+        if idx == 0 {
+            return None;
+        }
+        let e = &self.extents[idx - 1];
+        if e.is_uninit() {
+            return None;
+        }
+        e.map(lblk)
+    }
+
+    /// Reads the whole logical block `lblk`, zero filled if it is a hole.
+    pub fn read_logical_block(&mut self, lblk: u32) -> Result<Block> {
+        if let Some(data) = &self.inline_data {
+            let mut block = vec![0u8; self.disk.block_size as usize];
+            if lblk == 0 {
+                let len = data.len().min(block.len());
+                block[..len].copy_from_slice(&data[..len]);
+            }
+            return Ok(block.into());
+        }
+        match self.physical_block(lblk) {
+            Some(pblk) => self.disk.read_block(pblk),
+            None => Ok(vec![0u8; self.disk.block_size as usize].into()),
+        }
+    }
+}
+
+impl<D: BlockDevice> Read for FileHandle<'_, D> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos >= self.size || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let block_size = self.disk.block_size as u64;
+        let lblk = self.pos / block_size;
+        let offset_in_blk = (self.pos % block_size) as usize;
+        let n = (buf.len() as u64)
+            .min(block_size - offset_in_blk as u64)
+            .min(self.size - self.pos) as usize;
+
+        let lblk = u32::try_from(lblk).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Logical block out of range",
+            )
+        })?;
+        let block = self.read_logical_block(lblk)?;
+        buf[..n].copy_from_slice(&block[offset_in_blk..offset_in_blk + n]);
+
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<D: BlockDevice> Seek for FileHandle<'_, D> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::End(d) => self.size.checked_add_signed(d),
+            SeekFrom::Current(d) => self.pos.checked_add_signed(d),
+        };
+        match new_pos {
+            Some(p) => {
+                self.pos = p;
+                Ok(p)
+            }
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
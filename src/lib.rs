@@ -0,0 +1,8 @@
+pub mod cache;
+pub mod carve;
+pub mod device;
+pub mod disk;
+pub mod error;
+pub mod ext4;
+pub mod file;
+pub mod partition;
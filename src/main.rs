@@ -1,12 +1,13 @@
-mod disk;
-mod ext4;
-
 use crossterm::{
     event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use disk::Disk;
+use ext4_impl::device::BlockDevice;
+use ext4_impl::disk::{self, Disk};
+use ext4_impl::error::Ext4Error;
+use ext4_impl::ext4;
+use ext4_impl::partition::{self, PartitionKind};
 use std::{
     error::Error,
     io,
@@ -67,37 +68,89 @@ impl StatefulList {
 
 struct App {
     items: StatefulList,
-    disk: Disk,
+    disk: Disk<Box<dyn BlockDevice>>,
 }
 impl App {
-    fn new(path: &str) -> App {
-        let mut d = disk::Disk::new(path);
-        App {
-            items: StatefulList::with_items(d.read_dir(2)),
+    fn new(spec: &str) -> Result<App, Ext4Error> {
+        let mut d = open_disk(spec)?;
+        Ok(App {
+            items: StatefulList::with_items(d.read_dir(disk::ROOT_INODE)?),
             disk: d,
-        }
+        })
     }
 
-    fn load(&mut self) {
+    fn load(&mut self) -> Result<(), Ext4Error> {
         if let Some(x) = self.items.state.selected() {
             let entry = &self.items.items[x];
             if entry
                 .file_type
                 .intersects(ext4::flags::dir::FileType::Ext4FtDir)
             {
-                self.items = StatefulList::with_items(self.disk.read_dir(entry.inode));
+                self.items = StatefulList::with_items(self.disk.read_dir(entry.inode)?);
             }
         }
+        Ok(())
+    }
+}
+
+/// Opens the filesystem in `spec`, which names an image file, or partition
+/// N of a whole-disk image as `image@pN`.
+fn open_disk(spec: &str) -> Result<Disk<Box<dyn BlockDevice>>, Ext4Error> {
+    let partition = spec
+        .rsplit_once("@p")
+        .and_then(|(path, n)| Some((path, n.parse::<u32>().ok()?)))
+        .filter(|_| !std::path::Path::new(spec).exists());
+    let device: Box<dyn BlockDevice> = match partition {
+        Some((path, n)) => Box::new(partition::open_partition(std::fs::File::open(path)?, n)?),
+        None => Box::new(std::fs::File::open(spec)?),
+    };
+    Disk::from_device(device)
+}
+
+/// Lists the partitions of the whole-disk image `path`, if it has any, so
+/// that one can be picked with `@pN`.
+fn print_partitions(path: &str) {
+    let partitions = match std::fs::File::open(path)
+        .map_err(Ext4Error::from)
+        .and_then(|mut f| partition::read_partitions(&mut f))
+    {
+        Ok(partitions) if !partitions.is_empty() => partitions,
+        _ => return,
+    };
+    eprintln!("{} is partitioned, open one as {}@pN:", path, path);
+    for p in partitions {
+        let kind = match p.kind {
+            PartitionKind::Mbr { type_id } => format!("type {:#04x}", type_id),
+            PartitionKind::Gpt {
+                type_guid, name, ..
+            } => format!("{} {}", type_guid, name),
+        };
+        eprintln!(
+            "  p{:<3} offset {:>14}  size {:>14}  {}",
+            p.number, p.offset, p.size, kind
+        );
     }
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args: Vec<String> = std::env::args().collect();
     if args.len() == 1 {
-        println!("Usage: {} file_name", args[0]);
+        println!("Usage: {} file_name[@pN]", args[0]);
         return Ok(());
     }
 
+    // Open the image first so a bad one is reported on a normal terminal.
+    let app = match App::new(&args[1]) {
+        Ok(app) => app,
+        Err(e) => {
+            eprintln!("{}: {}", args[1], e);
+            if let Ext4Error::BadMagic(_) = e {
+                print_partitions(&args[1]);
+            }
+            std::process::exit(1);
+        }
+    };
+
     // setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -105,10 +158,9 @@ fn main() -> Result<(), Box<dyn Error>> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let app = App::new(&args[1]);
-    run_app(&mut terminal, app)?;
+    let res = run_app(&mut terminal, app);
 
-    // restore terminal
+    // restore terminal, even if the app failed
     disable_raw_mode()?;
     execute!(
         terminal.backend_mut(),
@@ -117,10 +169,14 @@ fn main() -> Result<(), Box<dyn Error>> {
     )?;
     terminal.show_cursor()?;
 
+    if let Err(e) = res {
+        eprintln!("{}: {}", args[1], e);
+        std::process::exit(1);
+    }
     Ok(())
 }
 
-fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<()> {
+fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> Result<(), Box<dyn Error>> {
     let tick_rate = Duration::from_millis(250);
     let mut last_tick = Instant::now();
 
@@ -137,7 +193,7 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<(
                     KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
                     KeyCode::Down | KeyCode::Char('j') => app.items.next(),
                     KeyCode::Up | KeyCode::Char('k') => app.items.previous(),
-                    KeyCode::Enter | KeyCode::Char('l') | KeyCode::Right => app.load(),
+                    KeyCode::Enter | KeyCode::Char('l') | KeyCode::Right => app.load()?,
                     KeyCode::Left | KeyCode::Char('h') => app.items.top(),
                     _ => {}
                 }
@@ -0,0 +1,578 @@
+use std::fmt;
+
+use crate::device::{BlockDevice, SubDevice};
+use crate::error::{Ext4Error, Result};
+use crate::ext4::Decoder;
+
+/// Sector size MBR offsets are counted in.
+const SECTOR_SIZE: u64 = 512;
+
+/// Offset of the four primary entries in an MBR or EBR.
+const MBR_ENTRIES_OFFSET: usize = 446;
+
+/// MBR type of the partition protecting a GPT disk.
+const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xEE;
+
+/// MBR types of extended partitions, which hold a chain of EBRs.
+const MBR_TYPES_EXTENDED: [u8; 3] = [0x05, 0x0F, 0x85];
+
+/// MBR type of Linux filesystems.
+pub const MBR_TYPE_LINUX: u8 = 0x83;
+
+/// EBRs followed before the chain is taken to be looping.
+const MAX_EBRS: u32 = 128;
+
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+
+/// Partition entries read before the GPT entry array is taken to be corrupt.
+const MAX_GPT_ENTRIES: u32 = 1024;
+
+/// GPT sector sizes tried, in order.
+const GPT_SECTOR_SIZES: [u64; 2] = [512, 4096];
+
+/// CRC32 (IEEE) lookup table, reflected polynomial, as used by GPT.
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB88320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data {
+        crc = CRC32_TABLE[((crc ^ b as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+/// A GPT GUID, kept in its on-disk byte order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Guid(pub [u8; 16]);
+
+impl Guid {
+    /// Type of Linux filesystem partitions, 0FC63DAF-8483-4772-8E79-3D69D8477DE4.
+    pub const LINUX_FILESYSTEM: Guid = Guid([
+        0xAF, 0x3D, 0xC6, 0x0F, 0x83, 0x84, 0x72, 0x47, 0x8E, 0x79, 0x3D, 0x69, 0xD8, 0x47, 0x7D,
+        0xE4,
+    ]);
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == [0; 16]
+    }
+}
+
+/// The first three fields are little-endian on disk, the rest big-endian.
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let g = &self.0;
+        write!(
+            f,
+            "{:08X}-{:04X}-{:04X}-{:02X}{:02X}-",
+            u32::from_le_bytes([g[0], g[1], g[2], g[3]]),
+            u16::from_le_bytes([g[4], g[5]]),
+            u16::from_le_bytes([g[6], g[7]]),
+            g[8],
+            g[9]
+        )?;
+        g[10..].iter().try_for_each(|b| write!(f, "{:02X}", b))
+    }
+}
+
+/// What a partition table says a partition holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PartitionKind {
+    /// A primary or logical MBR partition with its one-byte type.
+    Mbr { type_id: u8 },
+    /// A GPT partition.
+    Gpt {
+        type_guid: Guid,
+        unique_guid: Guid,
+        name: String,
+    },
+}
+
+/// One partition of a disk image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Partition {
+    /// Number as Linux would give it: 1 to 4 for MBR primary slots, 5 onward
+    /// for logical partitions, and the 1-based entry index for GPT.
+    pub number: u32,
+    /// Byte offset of the partition in the image.
+    pub offset: u64,
+    /// Size in bytes.
+    pub size: u64,
+    pub kind: PartitionKind,
+}
+
+impl Partition {
+    /// Whether the type marks a Linux filesystem, as ext4 partitions are.
+    pub fn is_linux_filesystem(&self) -> bool {
+        match &self.kind {
+            PartitionKind::Mbr { type_id } => *type_id == MBR_TYPE_LINUX,
+            PartitionKind::Gpt { type_guid, .. } => *type_guid == Guid::LINUX_FILESYSTEM,
+        }
+    }
+}
+
+/// One of the four entries of an MBR or EBR.
+struct MbrEntry {
+    type_id: u8,
+    start_lba: u32,
+    num_sectors: u32,
+}
+
+impl MbrEntry {
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        let _status: u8 = d.read()?;
+        let _chs_first: [u8; 3] = d.read()?;
+        let type_id = d.read()?;
+        let _chs_last: [u8; 3] = d.read()?;
+        Ok(MbrEntry {
+            type_id,
+            start_lba: d.read()?,
+            num_sectors: d.read()?,
+        })
+    }
+}
+
+/// The four entries of the MBR or EBR in `sector`, or `None` without the
+/// 0x55AA boot signature.
+fn mbr_entries(sector: &[u8]) -> Result<Option<[MbrEntry; 4]>> {
+    if sector[510..512] != [0x55, 0xAA] {
+        return Ok(None);
+    }
+    let mut d = Decoder::new(sector, MBR_ENTRIES_OFFSET, "MbrEntry");
+    Ok(Some([
+        MbrEntry::decode(&mut d)?,
+        MbrEntry::decode(&mut d)?,
+        MbrEntry::decode(&mut d)?,
+        MbrEntry::decode(&mut d)?,
+    ]))
+}
+
+fn read_sectors<D: BlockDevice + ?Sized>(
+    device: &mut D,
+    offset: u64,
+    len: usize,
+) -> Result<Vec<u8>> {
+    let mut buf = vec![0u8; len];
+    device.read_at(offset, &mut buf)?;
+    Ok(buf)
+}
+
+/// Lists the partitions of a whole-disk image, from its GPT if the MBR is
+/// protective and from the MBR and its extended partition chain otherwise.
+/// Extended partitions themselves are not listed, only the logical ones
+/// inside them. An image without an MBR boot signature has no partitions.
+pub fn read_partitions<D: BlockDevice + ?Sized>(device: &mut D) -> Result<Vec<Partition>> {
+    if device.size()? < SECTOR_SIZE {
+        return Ok(Vec::new());
+    }
+    let mbr = read_sectors(device, 0, SECTOR_SIZE as usize)?;
+    let entries = match mbr_entries(&mbr)? {
+        Some(entries) => entries,
+        None => return Ok(Vec::new()),
+    };
+    if entries.iter().any(|e| e.type_id == MBR_TYPE_GPT_PROTECTIVE) {
+        return read_gpt(device);
+    }
+
+    let mut partitions = Vec::new();
+    for (slot, e) in entries.iter().enumerate() {
+        if e.type_id == 0 || e.num_sectors == 0 {
+            continue;
+        }
+        if MBR_TYPES_EXTENDED.contains(&e.type_id) {
+            read_logical_partitions(device, e.start_lba as u64, &mut partitions)?;
+            continue;
+        }
+        partitions.push(Partition {
+            number: slot as u32 + 1,
+            offset: e.start_lba as u64 * SECTOR_SIZE,
+            size: e.num_sectors as u64 * SECTOR_SIZE,
+            kind: PartitionKind::Mbr { type_id: e.type_id },
+        });
+    }
+    partitions.sort_by_key(|p| p.number);
+    Ok(partitions)
+}
+
+/// Follows the EBR chain of the extended partition starting at sector
+/// `base`. Each EBR describes one logical partition, relative to itself,
+/// and links to the next EBR, relative to `base`.
+fn read_logical_partitions<D: BlockDevice + ?Sized>(
+    device: &mut D,
+    base: u64,
+    partitions: &mut Vec<Partition>,
+) -> Result<()> {
+    let mut ebr_lba = base;
+    let mut number = 5;
+    for _ in 0..MAX_EBRS {
+        let sector = read_sectors(device, ebr_lba * SECTOR_SIZE, SECTOR_SIZE as usize)?;
+        let [logical, next, ..] = match mbr_entries(&sector)? {
+            Some(entries) => entries,
+            None => {
+                return Err(Ext4Error::BadPartitionTable(format!(
+                    "no boot signature in EBR at sector {}",
+                    ebr_lba
+                )))
+            }
+        };
+        if logical.type_id != 0 && logical.num_sectors != 0 {
+            partitions.push(Partition {
+                number,
+                offset: (ebr_lba + logical.start_lba as u64) * SECTOR_SIZE,
+                size: logical.num_sectors as u64 * SECTOR_SIZE,
+                kind: PartitionKind::Mbr {
+                    type_id: logical.type_id,
+                },
+            });
+            number += 1;
+        }
+        if next.type_id == 0 || next.start_lba == 0 {
+            return Ok(());
+        }
+        ebr_lba = base + next.start_lba as u64;
+    }
+    Err(Ext4Error::BadPartitionTable(format!(
+        "EBR chain longer than {} entries",
+        MAX_EBRS
+    )))
+}
+
+/// The fields of a GPT header needed to find its entries.
+struct GptHeader {
+    backup_lba: u64,
+    entries_lba: u64,
+    num_entries: u32,
+    entry_size: u32,
+    entries_crc32: u32,
+}
+
+/// Decodes and checks the GPT header in `sector`, `None` if it is missing
+/// or damaged.
+fn gpt_header(sector: &[u8]) -> Result<Option<GptHeader>> {
+    let mut d = Decoder::new(sector, 0, "GptHeader");
+    if d.bytes(8)? != GPT_SIGNATURE {
+        return Ok(None);
+    }
+    let _revision: u32 = d.read()?;
+    let header_size: u32 = d.read()?;
+    let header_crc32: u32 = d.read()?;
+    let _reserved: u32 = d.read()?;
+    let _current_lba: u64 = d.read()?;
+    let backup_lba = d.read()?;
+    let _first_usable_lba: u64 = d.read()?;
+    let _last_usable_lba: u64 = d.read()?;
+    let _disk_guid: [u8; 16] = d.read()?;
+    let header = GptHeader {
+        backup_lba,
+        entries_lba: d.read()?,
+        num_entries: d.read()?,
+        entry_size: d.read()?,
+        entries_crc32: d.read()?,
+    };
+
+    // The CRC covers header_size bytes with its own field taken as zero.
+    let header_size = header_size as usize;
+    if !(92..=sector.len()).contains(&header_size) {
+        return Ok(None);
+    }
+    let mut raw = sector[..header_size].to_vec();
+    raw[16..20].fill(0);
+    // Entries are 128 << n bytes; anything past a page is taken as damage.
+    let entry_size = header.entry_size;
+    if crc32(&raw) != header_crc32
+        || !entry_size.is_power_of_two()
+        || !(128..=4096).contains(&entry_size)
+    {
+        return Ok(None);
+    }
+    Ok(Some(header))
+}
+
+/// Lists the partitions of a GPT disk, falling back to the backup header
+/// at the end of the disk if the primary one is damaged.
+fn read_gpt<D: BlockDevice + ?Sized>(device: &mut D) -> Result<Vec<Partition>> {
+    let disk_size = device.size()?;
+    for sector_size in GPT_SECTOR_SIZES {
+        if disk_size < 2 * sector_size {
+            continue;
+        }
+        let primary = read_sectors(device, sector_size, sector_size as usize)?;
+        let header = match gpt_header(&primary)? {
+            Some(header) => Some(header),
+            None => {
+                let last_lba = disk_size / sector_size - 1;
+                let backup = read_sectors(device, last_lba * sector_size, sector_size as usize)?;
+                gpt_header(&backup)?.filter(|h| h.backup_lba == 1)
+            }
+        };
+        if let Some(header) = header {
+            return read_gpt_entries(device, &header, sector_size);
+        }
+    }
+    Err(Ext4Error::BadPartitionTable(
+        "protective MBR without a valid GPT header".to_string(),
+    ))
+}
+
+fn read_gpt_entries<D: BlockDevice + ?Sized>(
+    device: &mut D,
+    header: &GptHeader,
+    sector_size: u64,
+) -> Result<Vec<Partition>> {
+    if header.num_entries > MAX_GPT_ENTRIES {
+        return Err(Ext4Error::BadPartitionTable(format!(
+            "{} GPT entries",
+            header.num_entries
+        )));
+    }
+    let entry_size = header.entry_size as usize;
+    let table_offset = header.entries_lba.checked_mul(sector_size).ok_or_else(|| {
+        Ext4Error::BadPartitionTable(format!(
+            "GPT entry array at LBA {} out of range",
+            header.entries_lba
+        ))
+    })?;
+    let table = read_sectors(
+        device,
+        table_offset,
+        header.num_entries as usize * entry_size,
+    )?;
+    if crc32(&table) != header.entries_crc32 {
+        return Err(Ext4Error::BadPartitionTable(
+            "GPT entry array checksum mismatch".to_string(),
+        ));
+    }
+
+    let mut partitions = Vec::new();
+    for (i, raw) in table.chunks_exact(entry_size).enumerate() {
+        let mut d = Decoder::new(raw, 0, "GptEntry");
+        let type_guid = Guid(d.read()?);
+        if type_guid.is_zero() {
+            continue;
+        }
+        let unique_guid = Guid(d.read()?);
+        let first_lba: u64 = d.read()?;
+        let last_lba: u64 = d.read()?;
+        let _attributes: u64 = d.read()?;
+        let name: [u16; 36] = d.read()?;
+        if last_lba < first_lba {
+            return Err(Ext4Error::BadPartitionTable(format!(
+                "GPT entry {} ends before it starts",
+                i + 1
+            )));
+        }
+        let out_of_range =
+            || Ext4Error::BadPartitionTable(format!("GPT entry {} out of range", i + 1));
+        let offset = first_lba
+            .checked_mul(sector_size)
+            .ok_or_else(out_of_range)?;
+        let size = (last_lba - first_lba)
+            .checked_add(1)
+            .and_then(|sectors| sectors.checked_mul(sector_size))
+            .ok_or_else(out_of_range)?;
+        let name_len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+        partitions.push(Partition {
+            number: i as u32 + 1,
+            offset,
+            size,
+            kind: PartitionKind::Gpt {
+                type_guid,
+                unique_guid,
+                name: String::from_utf16_lossy(&name[..name_len]),
+            },
+        });
+    }
+    Ok(partitions)
+}
+
+/// The partition numbered `number` of a whole-disk image, as a device of
+/// its own that a `Disk` can be opened on.
+pub fn open_partition<D: BlockDevice>(mut device: D, number: u32) -> Result<SubDevice<D>> {
+    let partition = read_partitions(&mut device)?
+        .into_iter()
+        .find(|p| p.number == number)
+        .ok_or_else(|| Ext4Error::NotFound(format!("partition {}", number)))?;
+    Ok(SubDevice::new(device, partition.offset, partition.size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const S: usize = SECTOR_SIZE as usize;
+
+    /// Fills MBR or EBR slot `slot` of the sector at `lba` and signs it.
+    fn set_mbr_entry(disk: &mut [u8], lba: usize, slot: usize, type_id: u8, start: u32, len: u32) {
+        let sector = &mut disk[lba * S..(lba + 1) * S];
+        let e = MBR_ENTRIES_OFFSET + slot * 16;
+        sector[e + 4] = type_id;
+        sector[e + 8..e + 12].copy_from_slice(&start.to_le_bytes());
+        sector[e + 12..e + 16].copy_from_slice(&len.to_le_bytes());
+        sector[510..512].copy_from_slice(&[0x55, 0xAA]);
+    }
+
+    #[test]
+    fn numbers_logical_partitions_from_five() {
+        let mut disk = vec![0u8; 200 * S];
+        set_mbr_entry(&mut disk, 0, 0, MBR_TYPE_LINUX, 1, 10);
+        set_mbr_entry(&mut disk, 0, 2, 0x05, 20, 100);
+        // Three EBRs; the middle one describes no partition and takes no number.
+        set_mbr_entry(&mut disk, 20, 0, MBR_TYPE_LINUX, 1, 9);
+        set_mbr_entry(&mut disk, 20, 1, 0x05, 20, 20);
+        set_mbr_entry(&mut disk, 40, 1, 0x05, 40, 20);
+        set_mbr_entry(&mut disk, 60, 0, 0x07, 2, 5);
+
+        let partitions = read_partitions(&mut disk).unwrap();
+        let found: Vec<_> = partitions
+            .iter()
+            .map(|p| (p.number, p.offset, p.size))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (1, 512, 10 * 512),
+                (5, 21 * 512, 9 * 512),
+                (6, 62 * 512, 5 * 512)
+            ]
+        );
+        assert!(partitions[1].is_linux_filesystem());
+        assert!(!partitions[2].is_linux_filesystem());
+    }
+
+    #[test]
+    fn rejects_looping_ebr_chains() {
+        let mut disk = vec![0u8; 64 * S];
+        set_mbr_entry(&mut disk, 0, 0, 0x0F, 8, 32);
+        set_mbr_entry(&mut disk, 8, 0, MBR_TYPE_LINUX, 1, 4);
+        set_mbr_entry(&mut disk, 8, 1, 0x05, 16, 8);
+        // The second EBR links back to itself.
+        set_mbr_entry(&mut disk, 24, 1, 0x05, 16, 8);
+        assert!(matches!(
+            read_partitions(&mut disk),
+            Err(Ext4Error::BadPartitionTable(_))
+        ));
+    }
+
+    #[test]
+    fn no_boot_signature_means_no_partitions() {
+        let mut disk = vec![0u8; 4 * S];
+        assert_eq!(read_partitions(&mut disk).unwrap(), []);
+    }
+
+    const GPT_ENTRIES: u32 = 4;
+
+    /// A GPT disk of `sectors` sectors of `sector_size` bytes whose second
+    /// entry is a Linux filesystem spanning `first..=last`. The backup
+    /// header keeps its entries just before it.
+    fn gpt_disk(sector_size: usize, sectors: u64, first: u64, last: u64) -> Vec<u8> {
+        let mut disk = vec![0u8; sectors as usize * sector_size];
+        set_mbr_entry(&mut disk, 0, 0, MBR_TYPE_GPT_PROTECTIVE, 1, u32::MAX);
+
+        let mut entries = vec![0u8; GPT_ENTRIES as usize * 128];
+        let e = &mut entries[128..256];
+        e[..16].copy_from_slice(&Guid::LINUX_FILESYSTEM.0);
+        e[16..32].copy_from_slice(&[0x11; 16]);
+        e[32..40].copy_from_slice(&first.to_le_bytes());
+        e[40..48].copy_from_slice(&last.to_le_bytes());
+        for (i, c) in "root".encode_utf16().enumerate() {
+            e[56 + 2 * i..58 + 2 * i].copy_from_slice(&c.to_le_bytes());
+        }
+
+        let last_lba = sectors - 1;
+        for (current, backup, entries_lba) in [(1, last_lba, 2), (last_lba, 1, last_lba - 1)] {
+            let start = entries_lba as usize * sector_size;
+            disk[start..start + entries.len()].copy_from_slice(&entries);
+
+            let mut h = vec![0u8; 92];
+            h[..8].copy_from_slice(GPT_SIGNATURE);
+            h[8..12].copy_from_slice(&0x0001_0000_u32.to_le_bytes());
+            h[12..16].copy_from_slice(&92_u32.to_le_bytes());
+            h[24..32].copy_from_slice(&current.to_le_bytes());
+            h[32..40].copy_from_slice(&backup.to_le_bytes());
+            h[72..80].copy_from_slice(&entries_lba.to_le_bytes());
+            h[80..84].copy_from_slice(&GPT_ENTRIES.to_le_bytes());
+            h[84..88].copy_from_slice(&128_u32.to_le_bytes());
+            h[88..92].copy_from_slice(&crc32(&entries).to_le_bytes());
+            let crc = crc32(&h);
+            h[16..20].copy_from_slice(&crc.to_le_bytes());
+            let start = current as usize * sector_size;
+            disk[start..start + h.len()].copy_from_slice(&h);
+        }
+        disk
+    }
+
+    fn only_partition(disk: &mut Vec<u8>) -> Partition {
+        let mut partitions = read_partitions(disk).unwrap();
+        assert_eq!(partitions.len(), 1);
+        partitions.remove(0)
+    }
+
+    #[test]
+    fn reads_gpt_entries() {
+        let mut disk = gpt_disk(512, 64, 10, 19);
+        let p = only_partition(&mut disk);
+        assert_eq!((p.number, p.offset, p.size), (2, 10 * 512, 10 * 512));
+        assert!(p.is_linux_filesystem());
+        match p.kind {
+            PartitionKind::Gpt {
+                unique_guid, name, ..
+            } => {
+                assert_eq!(unique_guid, Guid([0x11; 16]));
+                assert_eq!(name, "root");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn falls_back_to_the_backup_gpt_header() {
+        let mut disk = gpt_disk(512, 64, 10, 19);
+        // Damage the primary header and its entries alike.
+        disk[512 + 16] ^= 0xFF;
+        disk[2 * 512..3 * 512].fill(0xFF);
+        let p = only_partition(&mut disk);
+        assert_eq!((p.number, p.offset, p.size), (2, 10 * 512, 10 * 512));
+
+        // Without a valid backup either, the table is unreadable.
+        disk[63 * 512 + 16] ^= 0xFF;
+        assert!(matches!(
+            read_partitions(&mut disk),
+            Err(Ext4Error::BadPartitionTable(_))
+        ));
+    }
+
+    #[test]
+    fn reads_gpt_with_4k_sectors() {
+        let mut disk = gpt_disk(4096, 16, 4, 11);
+        let p = only_partition(&mut disk);
+        assert_eq!((p.number, p.offset, p.size), (2, 4 * 4096, 8 * 4096));
+    }
+
+    #[test]
+    fn rejects_gpt_entries_out_of_range() {
+        let mut disk = gpt_disk(512, 64, u64::MAX / 256, u64::MAX / 256 + 1);
+        match read_partitions(&mut disk) {
+            Err(Ext4Error::BadPartitionTable(reason)) => {
+                assert_eq!(reason, "GPT entry 2 out of range")
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
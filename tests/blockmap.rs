@@ -0,0 +1,74 @@
+//! Reads block-mapped inodes through their indirect blocks.
+//!
+//! tests/data/blockmap.img was made with e2fsprogs 1.47. Its 1 KiB blocks
+//! put blocks 12 to 267 of a file behind the indirect block and the rest
+//! behind the double indirect one, which src/big reaches; src/dir takes 22
+//! blocks without an index:
+//!
+//! ```text
+//! export E2FSPROGS_FAKE_TIME=1700000000
+//! mkdir -p src/dir
+//! python3 -c "
+//! f = open('src/big', 'wb')
+//! for i in range(300):
+//!     f.write(('block %04d\n' % i).encode().ljust(1024, b'.'))
+//! "
+//! for i in $(seq 1 600); do : > src/dir/entry-with-a-long-name-$i; done
+//! mke2fs -t ext2 -b 1024 -N 700 -O ^dir_index,^resize_inode \
+//!     -U 6c0e2ba1-3f4d-4a5e-9b61-2d8f7c9e0a13 -E root_owner=0:0 -d src blockmap.img 1M
+//! ```
+
+use std::io::{Read, Seek, SeekFrom};
+
+use ext4_impl::disk::Disk;
+
+fn open() -> Disk<Vec<u8>> {
+    let raw = std::fs::read(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/data/blockmap.img"
+    ))
+    .unwrap();
+    Disk::from_device(raw).unwrap()
+}
+
+fn block(i: usize) -> Vec<u8> {
+    let mut data = format!("block {:04}\n", i).into_bytes();
+    data.resize(1024, b'.');
+    data
+}
+
+#[test]
+fn reads_through_double_indirect_blocks() {
+    let mut disk = open();
+    let big = disk.resolve("/big").unwrap();
+    let mut data = Vec::new();
+    disk.open(big).unwrap().read_to_end(&mut data).unwrap();
+    assert_eq!(data.len(), 300 * 1024);
+    for (i, chunk) in data.chunks(1024).enumerate() {
+        assert_eq!(chunk, block(i));
+    }
+}
+
+#[test]
+fn seeks_into_double_indirect_blocks() {
+    let mut disk = open();
+    let big = disk.resolve("/big").unwrap();
+    let mut file = disk.open(big).unwrap();
+    file.seek(SeekFrom::Start(280 * 1024)).unwrap();
+    let mut chunk = vec![0; 1024];
+    file.read_exact(&mut chunk).unwrap();
+    assert_eq!(chunk, block(280));
+}
+
+#[test]
+fn lists_directories_through_indirect_blocks() {
+    let mut disk = open();
+    let dir = disk.resolve("/dir").unwrap();
+    let entries = disk.read_dir(dir).unwrap();
+    assert_eq!(entries.len(), 602);
+    for i in [1, 300, 600] {
+        let name = format!("entry-with-a-long-name-{}", i);
+        let entry = disk.lookup(dir, name.as_bytes()).unwrap().unwrap();
+        assert!(entries.iter().any(|e| e.inode == entry.inode));
+    }
+}
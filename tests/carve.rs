@@ -0,0 +1,67 @@
+//! Scans in-memory images for filesystems.
+
+use std::time::{Duration, Instant};
+
+use ext4_impl::carve::{find_filesystems, DEFAULT_ALIGN};
+
+fn image() -> Vec<u8> {
+    std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/small.img")).unwrap()
+}
+
+fn set_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+#[test]
+fn skips_filesystems_larger_than_the_device() {
+    // The superblock of small.img, regrown to 2^28 groups of 1024 blocks
+    // with 8 inodes each, which still passes the geometry checks.
+    let mut sb = image()[1024..2048].to_vec();
+    set_u32(&mut sb, 0x00, 8 << 28);
+    set_u32(&mut sb, 0x04, 1);
+    set_u32(&mut sb, 0x150, 0x40);
+    set_u32(&mut sb, 0x28, 8);
+
+    let mut device = vec![0u8; 64 << 10];
+    device[5 << 10..6 << 10].copy_from_slice(&sb);
+    let start = Instant::now();
+    assert!(find_filesystems(&mut device, DEFAULT_ALIGN)
+        .unwrap()
+        .is_empty());
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn finds_an_embedded_filesystem() {
+    let fs = image();
+    let offset = 3 * 4096 + 512;
+    let mut device = vec![0xAAu8; offset];
+    device.extend_from_slice(&fs);
+    device.extend_from_slice(&[0u8; 8192]);
+
+    let found = find_filesystems(&mut device, DEFAULT_ALIGN).unwrap();
+    assert_eq!(found.len(), 1);
+    let c = &found[0];
+    assert_eq!(c.offset, offset as u64);
+    assert_eq!(c.size(), fs.len() as u64);
+    assert_eq!(c.found_in_group, 0);
+    assert!(c.is_consistent());
+    let copies: Vec<_> = c.copies.iter().map(|c| (c.group, c.offset)).collect();
+    let offset = offset as u64;
+    assert_eq!(copies, [(0, offset + 1024), (1, offset + 1025 * 1024)]);
+}
+
+#[test]
+fn finds_a_filesystem_from_its_backup() {
+    let mut fs = image();
+    fs[1024..2048].fill(0);
+    let offset = 8192;
+    let mut device = vec![0u8; offset];
+    device.extend_from_slice(&fs);
+
+    let found = find_filesystems(&mut device, DEFAULT_ALIGN).unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].offset, offset as u64);
+    assert_eq!(found[0].found_in_group, 1);
+    assert!(!found[0].is_consistent());
+}
@@ -0,0 +1,288 @@
+//! Reads a small checked-in image.
+//!
+//! tests/data/small.img was made with e2fsprogs 1.47:
+//!
+//! ```text
+//! export E2FSPROGS_FAKE_TIME=1700000000
+//! mkdir -p src/dir
+//! for i in $(seq 1 300); do : > src/dir/entry-with-a-long-name-$i; done
+//! printf 'hello, world\n' > src/hello.txt
+//! for i in $(seq 0 499); do printf 'line %04d\n' $i; done > src/lines.txt
+//! ln -s hello.txt src/link
+//! ln -s dir/entry-with-a-long-name-7 src/deep
+//! mke2fs -t ext4 -b 1024 -g 1024 -N 1024 -O metadata_csum,^has_journal,^resize_inode \
+//!     -U 6c0e2ba1-3f4d-4a5e-9b61-2d8f7c9e0a13 \
+//!     -E hash_seed=1f2e3d4c-5b6a-4978-8695-a4b3c2d1e0f9,root_owner=0:0 -d src small.img 2M
+//! debugfs -w -R "ea_set /hello.txt user.comment greeting" small.img
+//! e2fsck -fyD small.img
+//! ```
+
+use std::io::Read;
+
+use ext4_impl::disk::{Disk, LinkTarget};
+use ext4_impl::error::Ext4Error;
+
+const ROOT: u32 = 2;
+const IMAGE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/small.img");
+
+fn image() -> Vec<u8> {
+    std::fs::read(IMAGE).unwrap()
+}
+
+fn open() -> Disk<Vec<u8>> {
+    Disk::from_device(image()).unwrap()
+}
+
+/// Inode of the entry called `name` in directory `dir`.
+fn find(disk: &mut Disk<Vec<u8>>, dir: u32, name: &str) -> u32 {
+    disk.read_dir(dir)
+        .unwrap()
+        .into_iter()
+        .find(|e| e.to_char() == name)
+        .unwrap()
+        .inode
+}
+
+fn read_file(disk: &mut Disk<Vec<u8>>, inode: u32) -> Vec<u8> {
+    let mut data = Vec::new();
+    disk.open(inode).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+/// Byte offset of the record of inode `inode` in the image.
+fn inode_offset(disk: &mut Disk<Vec<u8>>, inode: u32) -> usize {
+    let sb = &disk.super_block;
+    let group = (inode - 1) / sb.s_inodes_per_group;
+    let index = ((inode - 1) % sb.s_inodes_per_group) as usize;
+    let inode_size = sb.s_inode_size as usize;
+    let table = disk.get_itable_blk_num(group).unwrap() as usize;
+    table * disk.block_size as usize + index * inode_size
+}
+
+#[test]
+fn opens_from_memory() {
+    let disk = open();
+    assert_eq!(disk.block_size, 1024);
+    assert_eq!(disk.super_block.group_count(), 2);
+    assert_eq!(disk.super_block_group(), 0);
+}
+
+#[test]
+fn reads_files() {
+    let mut disk = open();
+    let hello = find(&mut disk, ROOT, "hello.txt");
+    assert_eq!(read_file(&mut disk, hello), b"hello, world\n");
+
+    let lines = find(&mut disk, ROOT, "lines.txt");
+    let lines = read_file(&mut disk, lines);
+    assert_eq!(lines.len(), 5000);
+    assert!(lines.starts_with(b"line 0000\n"));
+    assert!(lines.ends_with(b"line 0499\n"));
+}
+
+#[test]
+fn lists_the_root() {
+    let mut disk = open();
+    let mut names: Vec<_> = disk
+        .read_dir(ROOT)
+        .unwrap()
+        .iter()
+        .map(|e| e.to_char())
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        [
+            ".",
+            "..",
+            "deep",
+            "dir",
+            "hello.txt",
+            "lines.txt",
+            "link",
+            "lost+found"
+        ]
+    );
+}
+
+#[test]
+fn lists_directories_spanning_many_blocks() {
+    let mut disk = open();
+    let dir = find(&mut disk, ROOT, "dir");
+    let entries = disk.read_dir(dir).unwrap();
+    assert_eq!(entries.len(), 302);
+    for i in 1..=300 {
+        let name = format!("entry-with-a-long-name-{}", i);
+        assert!(entries.iter().any(|e| e.to_char() == name), "{}", name);
+    }
+}
+
+#[test]
+fn looks_up_names_in_an_htree() {
+    let mut disk = open();
+    let dir = find(&mut disk, ROOT, "dir");
+    for i in [1, 150, 300] {
+        let name = format!("entry-with-a-long-name-{}", i);
+        let entry = disk.lookup(dir, name.as_bytes()).unwrap().unwrap();
+        assert_eq!(entry.inode, find(&mut disk, dir, &name));
+    }
+    assert!(disk
+        .lookup(dir, b"entry-with-a-long-name-301")
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn rejects_hash_trees_deeper_than_the_format_allows() {
+    let mut disk = open();
+    let dir = find(&mut disk, ROOT, "dir");
+    let root = disk.open(dir).unwrap().physical_block(0).unwrap() as usize;
+
+    // Without largedir a root has at most one level of dx nodes below it.
+    let mut raw = image();
+    raw[root * 1024 + 0x1e] = 2;
+    let mut disk = Disk::from_device(raw).unwrap();
+    for result in [
+        disk.lookup(dir, b"entry-with-a-long-name-1").map(|_| ()),
+        disk.read_dir(dir).map(|_| ()),
+    ] {
+        match result {
+            Err(Ext4Error::Corrupt { reason, .. }) => assert!(reason.contains("too deep")),
+            other => panic!("expected a corrupt hash tree, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn resolves_paths_through_symlinks() {
+    let mut disk = open();
+    let hello = find(&mut disk, ROOT, "hello.txt");
+    assert_eq!(disk.resolve("/hello.txt").unwrap(), hello);
+    assert_eq!(disk.resolve("/link").unwrap(), hello);
+    assert_eq!(disk.resolve("/./dir/../link").unwrap(), hello);
+
+    let dir = find(&mut disk, ROOT, "dir");
+    let target = find(&mut disk, dir, "entry-with-a-long-name-7");
+    assert_eq!(disk.resolve("/deep").unwrap(), target);
+
+    match disk.resolve("/link/nope") {
+        Err(Ext4Error::NotADirectory(path)) => assert_eq!(path, "/link"),
+        other => panic!("unexpected {:?}", other),
+    }
+    match disk.resolve("/dir/nope") {
+        Err(Ext4Error::NotFound(path)) => assert_eq!(path, "/dir/nope"),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn reads_xattrs() {
+    let mut disk = open();
+    let hello = find(&mut disk, ROOT, "hello.txt");
+    assert_eq!(
+        disk.get_xattr(hello, "user.comment").unwrap(),
+        Some(b"greeting".to_vec())
+    );
+    assert_eq!(disk.get_xattr(hello, "user.missing").unwrap(), None);
+}
+
+#[test]
+fn rejects_self_referencing_ea_inodes() {
+    let mut disk = open();
+    let hello = find(&mut disk, ROOT, "hello.txt");
+    let offset = inode_offset(&mut disk, hello);
+
+    // Mark the inode as inline data and turn its user.comment entry into a
+    // system.data entry whose value lives in the inode itself.
+    let mut raw = image();
+    let record = &mut raw[offset..offset + 256];
+    record[0x23] |= 0x10;
+    let extra_isize = u16::from_le_bytes([record[0x80], record[0x81]]) as usize;
+    let entry = &mut record[128 + extra_isize + 4..];
+    entry[0] = 4;
+    entry[1] = 7;
+    entry[4..8].copy_from_slice(&hello.to_le_bytes());
+    entry[16..20].copy_from_slice(b"data");
+
+    let mut disk = Disk::from_device(raw).unwrap();
+    assert!(matches!(
+        disk.list_xattrs(hello),
+        Err(Ext4Error::Corrupt { .. })
+    ));
+    assert!(matches!(disk.open(hello), Err(Ext4Error::Corrupt { .. })));
+}
+
+/// small.img with the user.comment xattr of hello.txt turned into an
+/// access ACL whose value is `value`, of at most 8 bytes.
+fn with_acl(value: &[u8]) -> (Vec<u8>, u32) {
+    let mut disk = open();
+    let hello = find(&mut disk, ROOT, "hello.txt");
+    let offset = inode_offset(&mut disk, hello);
+
+    let mut raw = image();
+    let record = &mut raw[offset..offset + 256];
+    let extra_isize = u16::from_le_bytes([record[0x80], record[0x81]]) as usize;
+    let area = 128 + extra_isize + 4;
+    let value_offset = u16::from_le_bytes([record[area + 2], record[area + 3]]) as usize;
+    let entry = &mut record[area..];
+    entry[0] = 0;
+    entry[1] = 2;
+    entry[8..12].copy_from_slice(&(value.len() as u32).to_le_bytes());
+    record[area + value_offset..area + value_offset + value.len()].copy_from_slice(value);
+    (raw, hello)
+}
+
+#[test]
+fn decodes_acls() {
+    // Version 1, then user::rw-.
+    let (raw, hello) = with_acl(&[1, 0, 0, 0, 0x01, 0, 0x06, 0]);
+    let mut disk = Disk::from_device(raw).unwrap();
+    let acl = disk.get_acl(hello).unwrap().unwrap();
+    assert_eq!(acl.entries.len(), 1);
+    assert_eq!(disk.get_default_acl(hello).unwrap(), None);
+}
+
+#[test]
+fn rejects_truncated_acls() {
+    let (raw, hello) = with_acl(&[1, 0, 0, 0, 0x01, 0]);
+    let mut disk = Disk::from_device(raw).unwrap();
+    assert!(matches!(
+        disk.get_acl(hello),
+        Err(Ext4Error::Corrupt { .. })
+    ));
+    assert!(matches!(
+        disk.effective_perms(hello, 0, &[0]),
+        Err(Ext4Error::Corrupt { .. })
+    ));
+}
+
+#[test]
+fn reads_link_targets() {
+    let mut disk = open();
+    let link = find(&mut disk, ROOT, "link");
+    assert_eq!(
+        disk.read_link(link).unwrap(),
+        Some(LinkTarget::Plain(b"hello.txt".to_vec()))
+    );
+    let hello = find(&mut disk, ROOT, "hello.txt");
+    assert_eq!(disk.read_link(hello).unwrap(), None);
+}
+
+#[test]
+fn checksums_verify() {
+    let mut disk = open();
+    assert_eq!(disk.verify_checksums().unwrap(), []);
+}
+
+#[test]
+fn opens_from_a_backup_superblock() {
+    let mut damaged = image();
+    damaged[1024..2048].fill(0);
+
+    let mut disk = Disk::from_backup(damaged, 1025, 1024).unwrap();
+    assert_eq!(disk.super_block_group(), 1);
+    let hello = disk.resolve("/hello.txt").unwrap();
+    let mut data = Vec::new();
+    disk.open(hello).unwrap().read_to_end(&mut data).unwrap();
+    assert_eq!(data, b"hello, world\n");
+}
@@ -0,0 +1,67 @@
+//! Reads a file whose extent tree is two levels deep.
+//!
+//! tests/data/extents.img was made with e2fsprogs 1.47. src/sparse has a
+//! block of data in every other block of its first 800, so each one is an
+//! extent of its own and the 400 of them need a root, an index node and
+//! five leaves:
+//!
+//! ```text
+//! export E2FSPROGS_FAKE_TIME=1700000000
+//! mkdir src
+//! python3 -c "
+//! f = open('src/sparse', 'wb')
+//! for i in range(400):
+//!     f.seek(2 * i * 1024)
+//!     f.write(('block %04d\n' % i).encode().ljust(1024, b'.'))
+//! "
+//! mke2fs -t ext4 -b 1024 -N 64 -O metadata_csum,^has_journal,^resize_inode \
+//!     -U 6c0e2ba1-3f4d-4a5e-9b61-2d8f7c9e0a13 -E root_owner=0:0 -d src extents.img 1M
+//! ```
+
+use std::io::Read;
+
+use ext4_impl::disk::Disk;
+
+fn open() -> Disk<Vec<u8>> {
+    let raw = std::fs::read(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/data/extents.img"
+    ))
+    .unwrap();
+    Disk::from_device(raw).unwrap()
+}
+
+fn block(i: usize) -> Vec<u8> {
+    let mut data = format!("block {:04}\n", i).into_bytes();
+    data.resize(1024, b'.');
+    data
+}
+
+#[test]
+fn walks_every_leaf() {
+    let mut disk = open();
+    let sparse = disk.resolve("/sparse").unwrap();
+    let inode = disk.get_inode(sparse).unwrap();
+    let extents = disk.get_extents(&inode).unwrap();
+    assert_eq!(extents.len(), 400);
+    for (i, extent) in extents.iter().enumerate() {
+        assert_eq!(extent.ee_block, 2 * i as u32);
+        assert_eq!(extent.ee_len, 1);
+    }
+}
+
+#[test]
+fn reads_data_and_holes() {
+    let mut disk = open();
+    let sparse = disk.resolve("/sparse").unwrap();
+    let mut data = Vec::new();
+    disk.open(sparse).unwrap().read_to_end(&mut data).unwrap();
+    assert_eq!(data.len(), 799 * 1024);
+    for (i, chunk) in data.chunks(1024).enumerate() {
+        if i % 2 == 0 {
+            assert_eq!(chunk, block(i / 2));
+        } else {
+            assert!(chunk.iter().all(|&b| b == 0));
+        }
+    }
+}
@@ -0,0 +1,48 @@
+//! Falls back to a backup superblock without being told where it is.
+//!
+//! tests/data/fallback.img keeps the default 8192 blocks per group, so the
+//! backup in group 1 is where `Disk::from_device` looks for it. It was made
+//! with e2fsprogs 1.47:
+//!
+//! ```text
+//! export E2FSPROGS_FAKE_TIME=1700000000
+//! mkdir src
+//! printf 'hello, world\n' > src/hello.txt
+//! mke2fs -t ext4 -b 1024 -N 128 -O metadata_csum,^has_journal,^resize_inode \
+//!     -U 6c0e2ba1-3f4d-4a5e-9b61-2d8f7c9e0a13 -E root_owner=0:0 -d src fallback.img 9M
+//! ```
+
+use std::io::Read;
+
+use ext4_impl::disk::Disk;
+
+fn image() -> Vec<u8> {
+    std::fs::read(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/data/fallback.img"
+    ))
+    .unwrap()
+}
+
+#[test]
+fn uses_the_primary_when_intact() {
+    let disk = Disk::from_device(image()).unwrap();
+    assert_eq!(disk.super_block_group(), 0);
+}
+
+#[test]
+fn finds_the_backup_when_the_primary_is_gone() {
+    let mut damaged = image();
+    damaged[1024..2048].fill(0);
+
+    let backups = Disk::backup_super_blocks(&mut damaged).unwrap();
+    assert_eq!(backups.len(), 1);
+    assert_eq!(backups[0].block, 8193);
+
+    let mut disk = Disk::from_device(damaged).unwrap();
+    assert_eq!(disk.super_block_group(), 1);
+    let hello = disk.resolve("/hello.txt").unwrap();
+    let mut data = Vec::new();
+    disk.open(hello).unwrap().read_to_end(&mut data).unwrap();
+    assert_eq!(data, b"hello, world\n");
+}
@@ -0,0 +1,54 @@
+//! Reads a directory whose hash tree has a level of dx nodes under its root.
+//!
+//! tests/data/htree.img was made with e2fsprogs 1.47. A thousand names of
+//! 200 characters fill 250 leaves, more than the 123 a root block indexes:
+//!
+//! ```text
+//! export E2FSPROGS_FAKE_TIME=1700000000
+//! mkdir -p src/dir
+//! for i in $(seq 1 1000); do : > src/dir/$(printf '%0200d' $i); done
+//! mke2fs -t ext4 -b 1024 -N 1100 -O metadata_csum,^has_journal,^resize_inode \
+//!     -U 6c0e2ba1-3f4d-4a5e-9b61-2d8f7c9e0a13 \
+//!     -E hash_seed=1f2e3d4c-5b6a-4978-8695-a4b3c2d1e0f9,root_owner=0:0 -d src htree.img 2M
+//! e2fsck -fyD htree.img
+//! ```
+
+use ext4_impl::disk::Disk;
+
+fn open() -> Disk<Vec<u8>> {
+    let raw = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/htree.img")).unwrap();
+    Disk::from_device(raw).unwrap()
+}
+
+fn name(i: u32) -> String {
+    format!("{:0200}", i)
+}
+
+#[test]
+fn lists_every_leaf() {
+    let mut disk = open();
+    let dir = disk.resolve("/dir").unwrap();
+    let mut names: Vec<String> = disk
+        .read_dir(dir)
+        .unwrap()
+        .iter()
+        .map(|e| e.to_char())
+        .collect();
+    names.sort();
+    let mut expected: Vec<String> = (1..=1000).map(name).collect();
+    expected.extend([".".to_string(), "..".to_string()]);
+    expected.sort();
+    assert_eq!(names, expected);
+}
+
+#[test]
+fn looks_up_through_dx_nodes() {
+    let mut disk = open();
+    let dir = disk.resolve("/dir").unwrap();
+    let entries = disk.read_dir(dir).unwrap();
+    for entry in entries.iter().filter(|e| e.name().len() == 200) {
+        let found = disk.lookup(dir, entry.name()).unwrap().unwrap();
+        assert_eq!(found.inode, entry.inode);
+    }
+    assert!(disk.lookup(dir, name(1001).as_bytes()).unwrap().is_none());
+}
@@ -0,0 +1,74 @@
+//! Reads files and directories stored in their inodes.
+//!
+//! tests/data/inline.img was made with e2fsprogs 1.47. hello.txt fits in
+//! i_block, longer.txt continues in the "system.data" attribute, and small
+//! is a directory whose two entries fit in i_block:
+//!
+//! ```text
+//! export E2FSPROGS_FAKE_TIME=1700000000
+//! mkdir -p src/small
+//! printf 'hello, world\n' > src/hello.txt
+//! python3 -c "open('src/longer.txt', 'w').write('x' * 100 + '\n')"
+//! : > src/small/a
+//! : > src/small/b
+//! mke2fs -t ext4 -b 1024 -N 64 -O metadata_csum,inline_data,^has_journal,^resize_inode \
+//!     -U 6c0e2ba1-3f4d-4a5e-9b61-2d8f7c9e0a13 -E root_owner=0:0 -d src inline.img 1M
+//! ```
+
+use std::io::Read;
+
+use ext4_impl::disk::Disk;
+use ext4_impl::ext4::flags::inode::IFlags;
+
+const ROOT: u32 = 2;
+
+fn open() -> Disk<Vec<u8>> {
+    let raw = std::fs::read(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/data/inline.img"
+    ))
+    .unwrap();
+    Disk::from_device(raw).unwrap()
+}
+
+fn read_file(disk: &mut Disk<Vec<u8>>, path: &str) -> Vec<u8> {
+    let inode = disk.resolve(path).unwrap();
+    assert!(disk
+        .get_inode(inode)
+        .unwrap()
+        .i_flags
+        .contains(IFlags::Ext4InlineDataFl));
+    let mut data = Vec::new();
+    disk.open(inode).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+#[test]
+fn reads_inline_files() {
+    let mut disk = open();
+    assert_eq!(read_file(&mut disk, "/hello.txt"), b"hello, world\n");
+    let mut longer = vec![b'x'; 100];
+    longer.push(b'\n');
+    assert_eq!(read_file(&mut disk, "/longer.txt"), longer);
+}
+
+#[test]
+fn lists_inline_directories() {
+    let mut disk = open();
+    let small = disk.resolve("/small").unwrap();
+    assert!(disk
+        .get_inode(small)
+        .unwrap()
+        .i_flags
+        .contains(IFlags::Ext4InlineDataFl));
+
+    let entries = disk.read_dir(small).unwrap();
+    let names: Vec<String> = entries.iter().map(|e| e.to_char()).collect();
+    assert_eq!(names, [".", "..", "a", "b"]);
+    assert_eq!(entries[0].inode, small);
+    assert_eq!(entries[1].inode, ROOT);
+
+    let a = disk.lookup(small, b"a").unwrap().unwrap();
+    assert_eq!(disk.resolve("/small/a").unwrap(), a.inode);
+    assert_eq!(disk.resolve("/small/..").unwrap(), ROOT);
+}